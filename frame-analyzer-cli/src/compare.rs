@@ -0,0 +1,276 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Result, ensure};
+use frame_analyzer::{
+    Pid,
+    compare::{Comparison, Delta, Metric, Sample, Thresholds},
+    export::{SCHEMA_VERSION, SummaryRecord, Versioned},
+    replay,
+    stats::Window,
+    trace::TraceReader,
+};
+
+use crate::summary::AppSummary;
+
+/// Exit status of a regression beyond the thresholds, 1 is left to the other errors
+pub const REGRESSION_STATUS: i32 = 2;
+
+/// The comparison ran but metrics regressed beyond the thresholds
+#[derive(Debug)]
+pub struct Regressed(usize);
+
+impl fmt::Display for Regressed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} metric(s) regressed beyond the thresholds", self.0)
+    }
+}
+
+impl Error for Regressed {}
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The session before the change, a trace file or the JSON output of `stats`
+    base: PathBuf,
+    /// The session after the change, a trace file or the JSON output of `stats`
+    new: PathBuf,
+    /// Only compare this app, a package name or a pid
+    #[arg(short, long)]
+    app: Option<String>,
+    /// Largest tolerated regression of the fps & frametimes, in percent
+    #[arg(long, default_value_t = Thresholds::default().max_regression_percent)]
+    max_regression: f64,
+    /// Largest tolerated increase of the jank rates, in percentage points
+    #[arg(long, default_value_t = Thresholds::default().max_jank_rate_increase)]
+    max_jank_increase: f64,
+    /// Significance level of the Mann-Whitney U test on the frametimes
+    #[arg(long, default_value_t = Thresholds::default().alpha)]
+    alpha: f64,
+}
+
+/// One app of a session
+struct App {
+    pid: Pid,
+    /// The package name, or the pid if unknown
+    name: String,
+    sample: Sample,
+    /// `None` for summaries
+    frametimes: Option<Vec<Duration>>,
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let thresholds = Thresholds {
+        max_regression_percent: args.max_regression,
+        max_jank_rate_increase: args.max_jank_increase,
+        alpha: args.alpha,
+    };
+    let pairs = pair(load(&args.base)?, load(&args.new)?, args.app.as_deref())?;
+
+    let mut regressions = 0;
+    for (i, (base, new)) in pairs.iter().enumerate() {
+        let comparison = match (&base.frametimes, &new.frametimes) {
+            (Some(base_frametimes), Some(new_frametimes)) => Comparison::from_frametimes(
+                base_frametimes,
+                base.sample.janks,
+                new_frametimes,
+                new.sample.janks,
+            )
+            .expect("apps have frames"),
+            _ => Comparison::new(base.sample, new.sample),
+        };
+        let regressed = comparison.regressions(&thresholds);
+        regressions += regressed.len();
+
+        if i > 0 {
+            println!();
+        }
+        print(base, new, &comparison, &regressed);
+    }
+
+    if regressions > 0 {
+        return Err(Regressed(regressions).into());
+    }
+
+    Ok(())
+}
+
+fn print(base: &App, new: &App, comparison: &Comparison, regressed: &[Delta]) {
+    let name = if base.name == new.name {
+        base.name.clone()
+    } else {
+        format!("{} -> {}", base.name, new.name)
+    };
+    println!(
+        "{name}: {} -> {} frames",
+        comparison.base.summary.frames, comparison.new.summary.frames
+    );
+
+    println!(
+        "{:<18}{:>12}{:>12}{:>12}",
+        "metric", "base", "new", "change"
+    );
+    for delta in comparison.deltas() {
+        let change = if delta.metric.is_rate() {
+            format!("{:+.2} pp", delta.change())
+        } else {
+            delta
+                .percent()
+                .map_or_else(|| "-".into(), |percent| format!("{percent:+.2}%"))
+        };
+        let flag = if regressed.iter().any(|r| r.metric == delta.metric) {
+            "  regressed"
+        } else {
+            ""
+        };
+        println!(
+            "{:<18}{:>12}{:>12}{change:>12}{flag}",
+            delta.metric.name(),
+            value(delta.metric, delta.base),
+            value(delta.metric, delta.new),
+        );
+    }
+
+    match comparison.significance {
+        Some(test) => println!(
+            "Mann-Whitney U: p = {:.2e}, a new frame is longer than a base frame {:.1}% of the time",
+            test.p_value,
+            test.effect * 100.0
+        ),
+        None => println!("summaries only, no significance test"),
+    }
+}
+
+fn value(metric: Metric, value: f64) -> String {
+    match metric.unit() {
+        "%" => format!("{value:.2}%"),
+        unit => format!("{value:.2} {unit}"),
+    }
+}
+
+/// Pair the apps of the sessions: a single app on each side, otherwise by name.
+/// `app` matches the package name or the pid, summaries only know the pid
+fn pair(base: Vec<App>, new: Vec<App>, app: Option<&str>) -> Result<Vec<(App, App)>> {
+    let select = |apps: Vec<App>| -> Vec<App> {
+        apps.into_iter()
+            .filter(|a| app.is_none_or(|app| a.name == app || a.pid.to_string() == app))
+            .collect()
+    };
+    let (mut base, mut new) = (select(base), select(new));
+
+    if base.len() == 1 && new.len() == 1 {
+        return Ok(vec![(base.remove(0), new.remove(0))]);
+    }
+
+    let pairs: Vec<_> = base
+        .into_iter()
+        .filter_map(|base| {
+            let i = new.iter().position(|new| new.name == base.name)?;
+            Some((base, new.swap_remove(i)))
+        })
+        .collect();
+    ensure!(
+        !pairs.is_empty(),
+        "no app in common between the sessions, pick one with --app"
+    );
+
+    Ok(pairs)
+}
+
+/// Load a trace file, or JSON lines of [`SummaryRecord`]s
+fn load(path: &Path) -> Result<Vec<App>> {
+    let mut first = [0];
+    File::open(path)?.read_exact(&mut first)?;
+
+    if first[0] == b'{' {
+        summaries(path)
+    } else {
+        trace(path)
+    }
+}
+
+fn trace(path: &Path) -> Result<Vec<App>> {
+    let events = replay::events(TraceReader::open(path)?);
+    let metadata = events.metadata().clone();
+    let mut apps: BTreeMap<Pid, (AppSummary, Vec<Duration>)> = BTreeMap::new();
+
+    for event in events {
+        let event = event?;
+        let (summary, frametimes) = apps.entry(event.pid).or_insert_with(|| {
+            (
+                AppSummary::new(event.pid, Window::Duration(Duration::MAX)),
+                Vec::new(),
+            )
+        });
+        summary.push(&event);
+        frametimes.push(event.frametime);
+    }
+
+    Ok(apps
+        .into_iter()
+        .filter_map(|(pid, (summary, frametimes))| {
+            let record = summary.record()?;
+            Some(App {
+                pid,
+                name: metadata
+                    .package_name(pid)
+                    .map_or_else(|| pid.to_string(), str::to_owned),
+                sample: Sample::new(record.summary(), record.jank_counts()),
+                frametimes: Some(frametimes),
+            })
+        })
+        .collect())
+}
+
+fn summaries(path: &Path) -> Result<Vec<App>> {
+    // a `stats` or `watch` output, the latest record of a pid wins
+    let mut records = BTreeMap::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let versioned: Versioned<SummaryRecord> = serde_json::from_str(&line)?;
+        ensure!(
+            versioned.schema_version == SCHEMA_VERSION,
+            "{} has schema version {}, expected {SCHEMA_VERSION}",
+            path.display(),
+            versioned.schema_version
+        );
+        records.insert(versioned.record.pid, versioned.record);
+    }
+
+    Ok(records
+        .into_values()
+        .map(|record| App {
+            pid: record.pid,
+            name: record.pid.to_string(),
+            sample: Sample::new(record.summary(), record.jank_counts()),
+            frametimes: None,
+        })
+        .collect())
+}
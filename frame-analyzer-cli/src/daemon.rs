@@ -0,0 +1,47 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::path::PathBuf;
+
+use anyhow::Result;
+use frame_analyzer::daemon::Daemon;
+
+use crate::target;
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The socket to listen on
+    #[arg(short, long, default_value = "/data/local/tmp/frame-analyzer.sock")]
+    socket: PathBuf,
+    /// Let the users of this group id connect, e.g. 2000 for `shell`, only root can connect otherwise
+    #[arg(short, long)]
+    group: Option<u32>,
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let mut daemon = Daemon::bind(target::analyzer()?, &args.socket)?;
+    if let Some(gid) = args.group {
+        daemon.set_group(gid)?;
+    }
+    let running = crate::running()?;
+
+    eprintln!("listening on {}", args.socket.display());
+    daemon.run(&running)?;
+
+    Ok(())
+}
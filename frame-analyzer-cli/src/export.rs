@@ -0,0 +1,156 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    fs::File,
+    io::BufWriter,
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use frame_analyzer::{
+    FrameEvent, Pid,
+    export::{ChromeTraceWriter, CsvWriter, FrameRecord, JsonLinesWriter, PerfettoWriter},
+    jank::JankKind,
+    replay,
+    trace::TraceReader,
+};
+
+use crate::summary::{AppSummary, FRAME_WINDOW};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    /// Perfetto trace, for ui.perfetto.dev
+    Perfetto,
+    /// Chrome trace event JSON, for chrome://tracing
+    Chrome,
+    /// JSON Lines of the frames
+    Json,
+    /// CSV of the frames
+    Csv,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The trace file to convert
+    trace: PathBuf,
+    #[arg(short, long, value_enum)]
+    format: ExportFormat,
+    /// The file to write
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+type FileWriter = BufWriter<File>;
+
+/// The writers of the export formats
+trait Sink {
+    fn set_process_name(&mut self, _pid: Pid, _name: String) {}
+
+    fn write(&mut self, event: &FrameEvent, jank: JankKind) -> Result<()>;
+
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+impl Sink for PerfettoWriter<FileWriter> {
+    fn set_process_name(&mut self, pid: Pid, name: String) {
+        Self::set_process_name(self, pid, name);
+    }
+
+    fn write(&mut self, event: &FrameEvent, _: JankKind) -> Result<()> {
+        Ok(Self::write(self, event)?)
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        Self::finish(*self)?;
+        Ok(())
+    }
+}
+
+impl Sink for ChromeTraceWriter<FileWriter> {
+    fn set_process_name(&mut self, pid: Pid, name: String) {
+        Self::set_process_name(self, pid, name);
+    }
+
+    fn write(&mut self, event: &FrameEvent, _: JankKind) -> Result<()> {
+        Ok(Self::write(self, event)?)
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        Self::finish(*self)?;
+        Ok(())
+    }
+}
+
+impl Sink for JsonLinesWriter<FileWriter> {
+    fn write(&mut self, event: &FrameEvent, jank: JankKind) -> Result<()> {
+        Ok(Self::write(self, &FrameRecord::new(event, jank))?)
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        Self::finish(*self)?;
+        Ok(())
+    }
+}
+
+impl Sink for CsvWriter<FileWriter, FrameRecord> {
+    fn write(&mut self, event: &FrameEvent, jank: JankKind) -> Result<()> {
+        Ok(Self::write(self, &FrameRecord::new(event, jank))?)
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        Self::finish(*self)?;
+        Ok(())
+    }
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let events = replay::events(TraceReader::open(&args.trace)?);
+    let metadata = events.metadata().clone();
+    let mut sink: Box<dyn Sink> = match args.format {
+        ExportFormat::Perfetto => Box::new(PerfettoWriter::create(&args.output)?),
+        ExportFormat::Chrome => Box::new(ChromeTraceWriter::create(&args.output)?),
+        ExportFormat::Json => Box::new(JsonLinesWriter::create(&args.output)?),
+        ExportFormat::Csv => Box::new(CsvWriter::<_, FrameRecord>::create(&args.output)?),
+    };
+
+    let mut apps = HashMap::new();
+    let mut frames = 0_usize;
+    for event in events {
+        let event = event?;
+        let app = match apps.entry(event.pid) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                if let Some(name) = metadata.package_name(event.pid) {
+                    sink.set_process_name(event.pid, name.to_string());
+                }
+                entry.insert(AppSummary::new(event.pid, FRAME_WINDOW))
+            }
+        };
+
+        let jank = app.push(&event);
+        sink.write(&event, jank)?;
+        frames += 1;
+    }
+    sink.finish()?;
+
+    eprintln!("exported {frames} frames to {}", args.output.display());
+    Ok(())
+}
@@ -0,0 +1,98 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{path::Path, time::Duration};
+
+use anyhow::Result;
+use frame_analyzer::{
+    Analyzer, FrameEvent, Pid,
+    daemon::Client,
+    replay::{ReplaySource, ReplaySpeed},
+};
+
+use crate::target;
+
+/// Where the frames come from
+pub enum Feed {
+    /// The ebpf uprobes, or a replay
+    Local(Box<Analyzer>),
+    /// A daemon, see `frame-analyzer daemon`
+    Daemon(Client),
+}
+
+impl Feed {
+    /// Attach the apps with the ebpf uprobes, needs root
+    pub fn local() -> Result<Self> {
+        Ok(Self::Local(Box::new(target::analyzer()?)))
+    }
+
+    /// Replay a trace file
+    pub fn replay(trace: &Path, speed: ReplaySpeed) -> Result<Self> {
+        let analyzer = Analyzer::with_replay(ReplaySource::open(trace, speed)?)?;
+        Ok(Self::Local(Box::new(analyzer)))
+    }
+
+    /// Receive the frames from the daemon listening at `socket`
+    pub fn daemon(socket: &Path) -> Result<Self> {
+        Ok(Self::Daemon(Client::connect(socket)?))
+    }
+
+    pub fn attach(&mut self, pid: Pid) -> Result<()> {
+        match self {
+            Self::Local(analyzer) => analyzer.attach_app(pid)?,
+            Self::Daemon(client) => client.attach_app(pid)?,
+        }
+
+        Ok(())
+    }
+
+    pub fn detach(&mut self, pid: Pid) -> Result<()> {
+        match self {
+            Self::Local(analyzer) => analyzer.detach_app(pid)?,
+            Self::Daemon(client) => client.detach_app(pid)?,
+        }
+
+        Ok(())
+    }
+
+    pub fn recv_timeout(&mut self, time: Duration) -> Option<FrameEvent> {
+        match self {
+            Self::Local(analyzer) => analyzer.recv_event_timeout(time),
+            Self::Daemon(client) => client.recv_frame_timeout(time).map(|frame| {
+                let event = FrameEvent::new(
+                    frame.pid,
+                    frame.surface as usize,
+                    frame.timestamp_ns,
+                    frame.frametime(),
+                );
+                match frame.refresh_period() {
+                    Some(period) => event.with_refresh_period(period),
+                    None => event,
+                }
+            }),
+        }
+    }
+
+    /// Whether a replay reached its end
+    pub fn is_exhausted(&self) -> bool {
+        match self {
+            Self::Local(analyzer) => analyzer.is_exhausted(),
+            Self::Daemon(_) => false,
+        }
+    }
+}
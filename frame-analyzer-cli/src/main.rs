@@ -0,0 +1,117 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+mod compare;
+mod daemon;
+mod export;
+mod feed;
+mod output;
+mod parse;
+mod record;
+mod replay;
+mod report;
+mod stats;
+mod summary;
+mod target;
+mod tui;
+mod watch;
+
+use std::{
+    io, process,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+
+/// Track the frametime of Android apps
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Live summary of the attached apps
+    Watch(watch::Args),
+    /// Record the frames of apps to a trace file
+    Record(record::Args),
+    /// Replay a trace file, printing its frames
+    Replay(replay::Args),
+    /// Convert a trace file to another format
+    Export(export::Args),
+    /// Summarize a trace file
+    Stats(stats::Args),
+    /// Generate a Markdown or HTML report of a trace file
+    Report(report::Args),
+    /// Compare two sessions, exiting with status 2 if the new one regressed
+    Compare(compare::Args),
+    /// Live dashboard of the attached apps, apps can be attached & detached from the keyboard
+    Tui(tui::Args),
+    /// Serve the frames to unprivileged clients over a unix socket
+    Daemon(daemon::Args),
+}
+
+fn main() -> Result<()> {
+    let result = match Cli::parse().command {
+        Command::Watch(args) => watch::run(&args),
+        Command::Record(args) => record::run(&args),
+        Command::Replay(args) => replay::run(&args),
+        Command::Export(args) => export::run(&args),
+        Command::Stats(args) => stats::run(&args),
+        Command::Report(args) => report::run(&args),
+        Command::Compare(args) => compare::run(&args),
+        Command::Tui(args) => tui::run(&args),
+        Command::Daemon(args) => daemon::run(&args),
+    };
+
+    // e.g. piped to `head`, not an error
+    match result {
+        Err(e)
+            if e.downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        // a regression is not a failure to compare, scripts can tell them apart
+        Err(e) if e.is::<compare::Regressed>() => {
+            eprintln!("Error: {e}");
+            process::exit(compare::REGRESSION_STATUS)
+        }
+        result => result,
+    }
+}
+
+/// A flag cleared on ctrl-c
+fn running() -> Result<Arc<AtomicBool>> {
+    let running = Arc::new(AtomicBool::new(true));
+
+    {
+        let running = running.clone();
+        ctrlc::set_handler(move || {
+            running.store(false, Ordering::Release);
+        })?;
+    }
+
+    Ok(running)
+}
@@ -0,0 +1,147 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{
+    io::{self, IsTerminal, Stdout, Write},
+    time::Duration,
+};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use frame_analyzer::export::{CsvWriter, FrameRecord, JsonLinesWriter, Record, SummaryRecord};
+
+/// How the records are printed
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// Human readable table
+    #[default]
+    Text,
+    /// JSON Lines, one object per record
+    Json,
+    /// CSV with a header row
+    Csv,
+}
+
+/// A record printable as a row of a table
+pub trait Row: Record {
+    const HEADER: &'static str;
+
+    fn row(&self) -> String;
+}
+
+impl Row for FrameRecord {
+    const HEADER: &'static str = "     PID          SURFACE    FRAMETIME   JANK";
+
+    fn row(&self) -> String {
+        let jank = if self.big_jank {
+            "big jank"
+        } else if self.jank {
+            "jank"
+        } else {
+            ""
+        };
+
+        format!(
+            "{:>8} {:>#16x} {:>12} {jank:>6}",
+            self.pid,
+            self.surface,
+            format_ns(self.frametime_ns),
+        )
+    }
+}
+
+impl Row for SummaryRecord {
+    const HEADER: &'static str =
+        "     PID     FPS  1% LOW    MEDIAN       P99       MAX  FRAMES  JANK  BIG JANK";
+
+    fn row(&self) -> String {
+        format!(
+            "{:>8} {:>7.2} {:>7.2} {:>9} {:>9} {:>9} {:>7} {:>5} {:>9}",
+            self.pid,
+            self.average_fps,
+            self.low_1_percent_fps,
+            format_ns(self.median_ns),
+            format_ns(self.p99_ns),
+            format_ns(self.max_ns),
+            self.frames,
+            self.jank,
+            self.big_jank,
+        )
+    }
+}
+
+enum Sink<R: Record> {
+    Text(Stdout),
+    Json(JsonLinesWriter<Stdout>),
+    Csv(CsvWriter<Stdout, R>),
+}
+
+/// Prints records to stdout in a [`Format`]
+pub struct Output<R: Record> {
+    sink: Sink<R>,
+}
+
+impl<R: Row> Output<R> {
+    pub fn new(format: Format) -> Result<Self> {
+        let sink = match format {
+            Format::Text => Sink::Text(io::stdout()),
+            Format::Json => Sink::Json(JsonLinesWriter::new(io::stdout())),
+            Format::Csv => Sink::Csv(CsvWriter::new(io::stdout())?),
+        };
+
+        Ok(Self { sink })
+    }
+
+    /// Start a table, only the text format has a header for every table
+    ///
+    /// With `redraw` the previous table is cleared if stdout is a terminal
+    pub fn begin(&mut self, redraw: bool) -> Result<()> {
+        if let Sink::Text(stdout) = &mut self.sink {
+            if redraw && stdout.is_terminal() {
+                write!(stdout, "\x1b[2J\x1b[H")?;
+            }
+            writeln!(stdout, "{}", R::HEADER)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write(&mut self, record: &R) -> Result<()> {
+        match &mut self.sink {
+            Sink::Text(stdout) => writeln!(stdout, "{}", record.row())?,
+            Sink::Json(writer) => writer.write(record)?,
+            Sink::Csv(writer) => writer.write(record)?,
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        match &mut self.sink {
+            Sink::Text(stdout) => stdout.flush()?,
+            Sink::Json(writer) => writer.flush()?,
+            Sink::Csv(writer) => writer.flush()?,
+        }
+
+        Ok(())
+    }
+}
+
+fn format_ns(ns: u64) -> String {
+    format!("{:.2?}", Duration::from_nanos(ns))
+}
@@ -0,0 +1,35 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::time::Duration;
+
+/// Longest interval taken from the command line, so a tiny rate or a huge duration still fits a `Duration`
+const MAX_SECONDS: f64 = 86_400.0;
+
+/// Value parser of the flags requiring a positive finite number
+pub fn parse_positive(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(value) if value.is_finite() && value > 0.0 => Ok(value),
+        _ => Err(format!("expected a positive finite number, got {value}")),
+    }
+}
+
+/// A number of seconds from [`parse_positive`] as a `Duration`, at most a day
+pub fn seconds(seconds: f64) -> Duration {
+    Duration::from_secs_f64(seconds.min(MAX_SECONDS))
+}
@@ -0,0 +1,101 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{
+    path::PathBuf,
+    process::Command,
+    sync::atomic::Ordering,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use frame_analyzer::trace::{SessionMetadata, TraceWriter};
+
+use crate::{
+    parse::{self, parse_positive},
+    target::{self, Targets},
+};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    #[command(flatten)]
+    targets: Targets,
+    /// The trace file to write
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Stop after this many seconds instead of on ctrl-c
+    #[arg(short, long, value_parser = parse_positive)]
+    duration: Option<f64>,
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let (mut analyzer, pids) = args.targets.analyzer()?;
+    let running = crate::running()?;
+
+    let packages: Vec<_> = pids
+        .iter()
+        .filter_map(|&pid| Some((pid, target::package_of(pid)?)))
+        .collect();
+    let mut metadata = SessionMetadata {
+        device: getprop("ro.product.model").unwrap_or_default(),
+        package: packages
+            .iter()
+            .map(|(_, package)| package.as_str())
+            .collect::<Vec<_>>()
+            .join(","),
+        refresh_rate: analyzer
+            .refresh_period()
+            .map(|period| 1.0 / period.as_secs_f64()),
+        start_unix_ms: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis() as u64),
+        extra: Vec::new(),
+    };
+    for (pid, package) in &packages {
+        metadata.set_package_name(*pid, package.clone());
+    }
+    analyzer.start_recording(TraceWriter::create(&args.output, &metadata)?);
+
+    let end = args
+        .duration
+        .map(|duration| Instant::now() + parse::seconds(duration));
+    let mut frames = 0_usize;
+
+    while running.load(Ordering::Acquire) && end.is_none_or(|end| Instant::now() < end) {
+        if analyzer
+            .recv_event_timeout(Duration::from_millis(100))
+            .is_some()
+        {
+            frames += 1;
+        }
+    }
+
+    if let Some(recorder) = analyzer.stop_recording() {
+        recorder.finish()?;
+    }
+    eprintln!("recorded {frames} frames to {}", args.output.display());
+
+    Ok(())
+}
+
+/// An Android system property
+fn getprop(name: &str) -> Option<String> {
+    let output = Command::new("getprop").arg(name).output().ok()?;
+    let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!value.is_empty()).then_some(value)
+}
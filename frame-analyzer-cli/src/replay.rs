@@ -0,0 +1,102 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+};
+
+use anyhow::Result;
+use frame_analyzer::{
+    Analyzer, Pid,
+    export::FrameRecord,
+    replay::{ReplaySource, ReplaySpeed},
+    trace::TraceReader,
+};
+
+use crate::{
+    output::{Format, Output},
+    summary::{AppSummary, FRAME_WINDOW},
+};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The trace file to replay
+    trace: PathBuf,
+    /// `realtime`, `max`, or a factor of the real time, e.g. `2` is twice as fast
+    #[arg(short, long, value_parser = parse_speed, default_value = "realtime")]
+    speed: ReplaySpeed,
+    /// Only replay the frames of this pid, can be repeated
+    #[arg(short, long)]
+    pid: Vec<Pid>,
+    #[arg(short, long, value_enum, default_value_t)]
+    format: Format,
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let mut analyzer = Analyzer::with_replay(ReplaySource::open(&args.trace, args.speed)?)?;
+    let pids = if args.pid.is_empty() {
+        trace_pids(&args.trace)?
+    } else {
+        args.pid.iter().copied().collect()
+    };
+    for pid in pids {
+        analyzer.attach_app(pid)?;
+    }
+    let mut output = Output::<FrameRecord>::new(args.format)?;
+    let running = crate::running()?;
+    let mut apps = HashMap::new();
+
+    output.begin(false)?;
+    while running.load(Ordering::Acquire) && !analyzer.is_exhausted() {
+        if let Some(event) = analyzer.recv_event() {
+            let jank = apps
+                .entry(event.pid)
+                .or_insert_with(|| AppSummary::new(event.pid, FRAME_WINDOW))
+                .push(&event);
+            output.write(&FrameRecord::new(&event, jank))?;
+        }
+    }
+    output.flush()?;
+
+    Ok(())
+}
+
+/// Every pid of a trace file
+pub fn trace_pids(trace: &Path) -> Result<BTreeSet<Pid>> {
+    let mut pids = BTreeSet::new();
+    for record in TraceReader::open(trace)? {
+        pids.insert(record?.pid);
+    }
+
+    Ok(pids)
+}
+
+pub fn parse_speed(speed: &str) -> Result<ReplaySpeed, String> {
+    match speed {
+        "realtime" => Ok(ReplaySpeed::RealTime),
+        "max" => Ok(ReplaySpeed::Max),
+        factor => match factor.parse::<f64>() {
+            Ok(factor) if ReplaySpeed::Scaled(factor).is_valid() => Ok(ReplaySpeed::Scaled(factor)),
+            _ => Err(format!(
+                "expected realtime, max or a positive finite factor, got {factor}"
+            )),
+        },
+    }
+}
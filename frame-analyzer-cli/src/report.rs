@@ -0,0 +1,69 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{fs, path::PathBuf};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use frame_analyzer::{report::Report, trace::TraceReader};
+
+use crate::parse::{self, parse_positive};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum ReportFormat {
+    #[default]
+    Markdown,
+    /// Self-contained page with fps charts
+    Html,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The trace file to report on
+    trace: PathBuf,
+    #[arg(short, long, value_enum, default_value_t)]
+    format: ReportFormat,
+    /// The file to write, stdout by default
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// The fps the apps are expected to reach, the refresh rate of the session by default
+    #[arg(short, long, value_parser = parse_positive)]
+    target_fps: Option<f64>,
+    /// Seconds per sample of the fps over time
+    #[arg(short, long, value_parser = parse_positive, default_value_t = 1.0)]
+    interval: f64,
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let mut builder = Report::builder().interval(parse::seconds(args.interval));
+    if let Some(target_fps) = args.target_fps {
+        builder = builder.target_fps(target_fps);
+    }
+    let report = builder.build(TraceReader::open(&args.trace)?)?;
+
+    let rendered = match args.format {
+        ReportFormat::Markdown => report.markdown(),
+        ReportFormat::Html => report.html(),
+    };
+    match &args.output {
+        Some(path) => fs::write(path, rendered)?,
+        None => print!("{rendered}"),
+    }
+
+    Ok(())
+}
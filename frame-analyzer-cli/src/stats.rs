@@ -0,0 +1,66 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
+
+use anyhow::Result;
+use frame_analyzer::{export::SummaryRecord, replay, stats::Window, trace::TraceReader};
+
+use crate::{
+    output::{Format, Output},
+    summary::AppSummary,
+};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    /// The trace file to summarize
+    trace: PathBuf,
+    #[arg(short, long, value_enum, default_value_t)]
+    format: Format,
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let events = replay::events(TraceReader::open(&args.trace)?);
+    let metadata = events.metadata().clone();
+    let mut apps = BTreeMap::new();
+
+    for event in events {
+        let event = event?;
+        apps.entry(event.pid)
+            .or_insert_with(|| AppSummary::new(event.pid, Window::Duration(Duration::MAX)))
+            .push(&event);
+    }
+
+    if args.format == Format::Text {
+        println!("device: {}", metadata.device);
+        println!("package: {}", metadata.package);
+        if let Some(refresh_rate) = metadata.refresh_rate {
+            println!("refresh rate: {refresh_rate:.2}Hz");
+        }
+        println!();
+    }
+
+    let mut output = Output::<SummaryRecord>::new(args.format)?;
+    output.begin(false)?;
+    for record in apps.values().filter_map(AppSummary::record) {
+        output.write(&record)?;
+    }
+    output.flush()?;
+
+    Ok(())
+}
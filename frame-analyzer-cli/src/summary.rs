@@ -0,0 +1,81 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use frame_analyzer::{
+    FrameEvent, Pid,
+    export::SummaryRecord,
+    jank::{JankCounts, JankDetector, JankKind, JankRule},
+    stats::{FrameStats, Window},
+    target_fps::{DEFAULT_CANDIDATES, TargetFpsEstimator},
+};
+
+/// Window of the per-frame jank classification, the PerfDog rule only looks at the latest frames
+pub const FRAME_WINDOW: Window = Window::Frames(4);
+
+/// Frames to infer a frame rate cap from, short so that the vsync rule applies soon
+const TARGET_FPS_WINDOW: usize = 30;
+
+/// The statistics & janks of one app
+pub struct AppSummary {
+    pid: Pid,
+    stats: FrameStats,
+    jank: JankDetector,
+    target: TargetFpsEstimator,
+    last_timestamp_ns: u64,
+}
+
+impl AppSummary {
+    pub fn new(pid: Pid, window: Window) -> Self {
+        Self {
+            pid,
+            stats: FrameStats::new(window),
+            jank: JankDetector::new(JankRule::PerfDog, window),
+            target: TargetFpsEstimator::with_candidates(DEFAULT_CANDIDATES, TARGET_FPS_WINDOW),
+            last_timestamp_ns: 0,
+        }
+    }
+
+    /// Account a frame, returns its jank classification, see [`JankRule::capped`]
+    pub fn push(&mut self, event: &FrameEvent) -> JankKind {
+        self.target.push(event);
+        self.jank.set_rule(JankRule::capped(
+            event.refresh_period,
+            self.target.estimate(),
+        ));
+
+        self.stats.push(event);
+        self.last_timestamp_ns = event.timestamp_ns;
+        self.jank.push(event)
+    }
+
+    /// The janks since the first frame
+    pub const fn total_janks(&self) -> JankCounts {
+        self.jank.total()
+    }
+
+    /// The statistics over the window, `None` without frames
+    pub fn record(&self) -> Option<SummaryRecord> {
+        let summary = self.stats.summary()?;
+        Some(SummaryRecord::new(
+            self.last_timestamp_ns,
+            self.pid,
+            &summary,
+            self.jank.counts(),
+        ))
+    }
+}
@@ -0,0 +1,103 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{fs, time::Duration};
+
+use anyhow::{Result, bail};
+use frame_analyzer::{
+    Analyzer, Pid,
+    refresh::{DumpsysRefreshRate, SysfsRefreshRate},
+};
+
+/// The apps to attach
+#[derive(clap::Args, Debug)]
+pub struct Targets {
+    /// The pid of a target application, can be repeated
+    #[arg(short, long)]
+    pub pid: Vec<Pid>,
+    /// The package name of a target application, can be repeated
+    #[arg(short = 'P', long)]
+    pub package: Vec<String>,
+}
+
+impl Targets {
+    pub fn is_empty(&self) -> bool {
+        self.pid.is_empty() && self.package.is_empty()
+    }
+
+    /// The pids of the targets, the packages are resolved to the pids running them
+    pub fn resolve(&self) -> Result<Vec<Pid>> {
+        let mut pids = self.pid.clone();
+        for package in &self.package {
+            let Some(pid) = find_package(package) else {
+                bail!("{package} is not running");
+            };
+            pids.push(pid);
+        }
+
+        if pids.is_empty() {
+            bail!("no target, see --pid & --package");
+        }
+
+        pids.sort_unstable();
+        pids.dedup();
+        Ok(pids)
+    }
+
+    /// An analyzer attached to the targets, discovering the display refresh rate
+    pub fn analyzer(&self) -> Result<(Analyzer, Vec<Pid>)> {
+        let pids = self.resolve()?;
+        let mut analyzer = analyzer()?;
+        for &pid in &pids {
+            analyzer.attach_app(pid)?;
+        }
+
+        Ok((analyzer, pids))
+    }
+}
+
+/// An analyzer discovering the display refresh rate
+pub fn analyzer() -> Result<Analyzer> {
+    let mut analyzer = Analyzer::new()?;
+    if let Some(sysfs) = SysfsRefreshRate::discover() {
+        analyzer.set_refresh_rate_provider(sysfs, Duration::from_millis(500));
+    } else {
+        analyzer.set_refresh_rate_provider(DumpsysRefreshRate, Duration::from_secs(5));
+    }
+
+    Ok(analyzer)
+}
+
+/// The pid of the process started as `package`
+pub fn find_package(package: &str) -> Option<Pid> {
+    fs::read_dir("/proc")
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<Pid>().ok())
+        .find(|pid| package_of(*pid).is_some_and(|name| name == package))
+}
+
+/// The package name of an app, i.e. the first argument of its command line
+pub fn package_of(pid: Pid) -> Option<String> {
+    let cmdline = fs::read(format!("/proc/{pid}/cmdline")).ok()?;
+    let name = cmdline.split(|&byte| byte == 0).next()?;
+    if name.is_empty() {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(name).into_owned())
+}
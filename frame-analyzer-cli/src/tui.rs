@@ -0,0 +1,391 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use frame_analyzer::{
+    FrameEvent, Pid,
+    replay::ReplaySpeed,
+    stats::{FrameStats, Window},
+    trace::TraceReader,
+};
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, Paragraph, Sparkline},
+};
+
+use crate::{
+    feed::Feed,
+    parse::{self, parse_positive},
+    replay::{self, parse_speed},
+    summary::AppSummary,
+    target::{self, Targets},
+};
+
+/// Window of the current fps
+const CURRENT_WINDOW: Duration = Duration::from_secs(1);
+/// Longest wait for frames before the keyboard is read
+const INPUT_LATENCY: Duration = Duration::from_millis(20);
+/// Frametimes kept for the sparklines, more than a terminal is wide
+const SPARKLINE_FRAMES: usize = 512;
+/// Height of the panel of an app, borders included
+const PANEL_HEIGHT: u16 = 4;
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    #[command(flatten)]
+    targets: Targets,
+    /// Replay this trace file instead of attaching the apps, every pid of the trace is attached by default
+    #[arg(short, long, conflicts_with = "daemon")]
+    replay: Option<PathBuf>,
+    /// Speed of the replay, `realtime`, `max`, or a factor of the real time
+    #[arg(short, long, value_parser = parse_speed, default_value = "realtime")]
+    speed: ReplaySpeed,
+    /// Receive the frames from a daemon at this socket instead of attaching the apps, needs no root
+    #[arg(short, long)]
+    daemon: Option<PathBuf>,
+    /// Redraws per second
+    #[arg(long, value_parser = parse_positive, default_value_t = 4.0)]
+    refresh_rate: f64,
+    /// Seconds of frames behind the average fps & 1% low
+    #[arg(short, long, value_parser = parse_positive, default_value_t = 60.0)]
+    window: f64,
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let mut names = HashMap::new();
+    let (feed, pids) = if let Some(trace) = &args.replay {
+        let metadata = TraceReader::open(trace)?.metadata().clone();
+        let pids = if args.targets.is_empty() {
+            replay::trace_pids(trace)?.into_iter().collect()
+        } else {
+            args.targets.resolve()?
+        };
+        for &pid in &pids {
+            if let Some(name) = metadata.package_name(pid) {
+                names.insert(pid, name.to_string());
+            }
+        }
+
+        (Feed::replay(trace, args.speed)?, pids)
+    } else {
+        let feed = match &args.daemon {
+            Some(socket) => Feed::daemon(socket)?,
+            None => Feed::local()?,
+        };
+        let pids = if args.targets.is_empty() {
+            Vec::new()
+        } else {
+            args.targets.resolve()?
+        };
+
+        (feed, pids)
+    };
+
+    let mut dashboard = Dashboard {
+        feed,
+        names,
+        apps: BTreeMap::new(),
+        window: parse::seconds(args.window),
+        selected: 0,
+        input: None,
+        status: String::new(),
+        quit: false,
+    };
+    for pid in pids {
+        dashboard.attach(pid);
+    }
+
+    let mut terminal = ratatui::init();
+    let result = dashboard.run(&mut terminal, parse::seconds(1.0 / args.refresh_rate));
+    ratatui::restore();
+
+    result
+}
+
+/// What the dashboard shows of an app
+struct AppView {
+    package: Option<String>,
+    surface: Option<usize>,
+    current: FrameStats,
+    summary: AppSummary,
+    recent: VecDeque<u64>,
+    last_frame: Option<Instant>,
+}
+
+impl AppView {
+    fn new(pid: Pid, package: Option<String>, window: Duration) -> Self {
+        Self {
+            package,
+            surface: None,
+            current: FrameStats::new(Window::Duration(CURRENT_WINDOW)),
+            summary: AppSummary::new(pid, Window::Duration(window)),
+            recent: VecDeque::with_capacity(SPARKLINE_FRAMES),
+            last_frame: None,
+        }
+    }
+
+    fn push(&mut self, event: &FrameEvent) {
+        self.surface = Some(event.surface);
+        self.current.push(event);
+        self.summary.push(event);
+
+        if self.recent.len() == SPARKLINE_FRAMES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(event.frametime.as_micros() as u64);
+        self.last_frame = Some(Instant::now());
+    }
+
+    /// The fps over the last second, `0.0` once the app stopped drawing
+    fn current_fps(&self) -> f64 {
+        self.last_frame
+            .filter(|last| last.elapsed() < CURRENT_WINDOW)
+            .and_then(|_| self.current.average_fps())
+            .unwrap_or_default()
+    }
+}
+
+struct Dashboard {
+    feed: Feed,
+    names: HashMap<Pid, String>,
+    apps: BTreeMap<Pid, AppView>,
+    window: Duration,
+    selected: usize,
+    /// The pid or package being typed to attach it
+    input: Option<String>,
+    status: String,
+    quit: bool,
+}
+
+impl Dashboard {
+    fn run(&mut self, terminal: &mut DefaultTerminal, redraw: Duration) -> Result<()> {
+        let mut next_draw = Instant::now();
+
+        while !self.quit {
+            if Instant::now() >= next_draw {
+                terminal.draw(|frame| self.draw(frame))?;
+                next_draw = Instant::now() + redraw;
+            }
+
+            while event::poll(Duration::ZERO)? {
+                if let Event::Key(key) = event::read()?
+                    && key.kind == KeyEventKind::Press
+                {
+                    self.key(key);
+                }
+            }
+
+            let timeout = next_draw
+                .saturating_duration_since(Instant::now())
+                .min(INPUT_LATENCY);
+            if self.apps.is_empty() || self.feed.is_exhausted() {
+                if self.feed.is_exhausted() {
+                    "replay finished".clone_into(&mut self.status);
+                }
+                // nothing to receive, wait for the keyboard instead
+                event::poll(timeout)?;
+            } else if let Some(event) = self.feed.recv_timeout(timeout) {
+                self.push(&event);
+                while let Some(event) = self.feed.recv_timeout(Duration::ZERO) {
+                    self.push(&event);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, event: &FrameEvent) {
+        if let Some(app) = self.apps.get_mut(&event.pid) {
+            app.push(event);
+        }
+    }
+
+    fn attach(&mut self, pid: Pid) {
+        if self.apps.contains_key(&pid) {
+            return;
+        }
+
+        match self.feed.attach(pid) {
+            Ok(()) => {
+                let package = self
+                    .names
+                    .get(&pid)
+                    .cloned()
+                    .or_else(|| target::package_of(pid));
+                self.apps
+                    .insert(pid, AppView::new(pid, package, self.window));
+                self.status = format!("attached {pid}");
+            }
+            Err(e) => self.status = format!("failed to attach {pid}: {e}"),
+        }
+    }
+
+    fn detach_selected(&mut self) {
+        let Some(&pid) = self.apps.keys().nth(self.selected) else {
+            return;
+        };
+
+        self.apps.remove(&pid);
+        self.selected = self.selected.min(self.apps.len().saturating_sub(1));
+        self.status = match self.feed.detach(pid) {
+            Ok(()) => format!("detached {pid}"),
+            Err(e) => format!("failed to detach {pid}: {e}"),
+        };
+    }
+
+    fn key(&mut self, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+
+        if let Some(input) = &mut self.input {
+            match key.code {
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Enter => {
+                    let input = input.trim().to_string();
+                    self.input = None;
+                    match input.parse().ok().or_else(|| target::find_package(&input)) {
+                        Some(pid) => self.attach(pid),
+                        None => self.status = format!("{input} is not running"),
+                    }
+                }
+                KeyCode::Esc => self.input = None,
+                _ => (),
+            }
+            return;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char('a') => self.input = Some(String::new()),
+            KeyCode::Char('d') | KeyCode::Delete => self.detach_selected(),
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(self.apps.len().saturating_sub(1));
+            }
+            _ => (),
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [main, footer] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+
+        if self.apps.is_empty() {
+            frame.render_widget(
+                Paragraph::new("no app attached, press a to attach one").centered(),
+                main,
+            );
+        } else {
+            // scroll so that the selected app is visible
+            let visible = usize::from((main.height / PANEL_HEIGHT).max(1));
+            let first = self.selected.saturating_sub(visible - 1);
+            let panels =
+                Layout::vertical(vec![Constraint::Length(PANEL_HEIGHT); visible]).split(main);
+
+            for ((index, (pid, app)), area) in
+                self.apps.iter().enumerate().skip(first).zip(panels.iter())
+            {
+                draw_app(frame, *area, *pid, app, index == self.selected);
+            }
+        }
+
+        let footer_line = self.input.as_ref().map_or_else(
+            || {
+                Line::from(vec![
+                    "a".bold(),
+                    " attach  ".into(),
+                    "d".bold(),
+                    " detach  ".into(),
+                    "↑↓".bold(),
+                    " select  ".into(),
+                    "q".bold(),
+                    " quit  ".into(),
+                    self.status.as_str().dark_gray(),
+                ])
+            },
+            |input| Line::from(format!("attach pid or package: {input}_")),
+        );
+        frame.render_widget(footer_line, footer);
+    }
+}
+
+fn draw_app(frame: &mut Frame, area: Rect, pid: Pid, app: &AppView, selected: bool) {
+    let title = format!(" {pid} {} ", app.package.as_deref().unwrap_or_default());
+    let surface = app
+        .surface
+        .map_or_else(String::new, |surface| format!(" surface {surface:#x} "));
+    let block = Block::bordered()
+        .title(title)
+        .title(Line::from(surface).right_aligned())
+        .border_style(if selected {
+            Style::new().fg(Color::Yellow)
+        } else {
+            Style::new()
+        });
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let [stats_area, sparkline_area] =
+        Layout::horizontal([Constraint::Length(40), Constraint::Min(0)]).areas(inner);
+
+    let record = app.summary.record();
+    let janks = app.summary.total_janks();
+    let stats = vec![
+        Line::from(format!(
+            "fps {:>6.1}  avg {:>6.1}  1% low {:>6.1}",
+            app.current_fps(),
+            record.map_or(0.0, |record| record.average_fps),
+            record.map_or(0.0, |record| record.low_1_percent_fps),
+        )),
+        Line::from(format!(
+            "jank {:>6}  big jank {:>6}",
+            janks.jank, janks.big_jank
+        )),
+    ];
+    frame.render_widget(Paragraph::new(stats), stats_area);
+
+    let width = usize::from(sparkline_area.width);
+    let recent: Vec<u64> = app
+        .recent
+        .iter()
+        .skip(app.recent.len().saturating_sub(width))
+        .copied()
+        .collect();
+    frame.render_widget(
+        Sparkline::default()
+            .data(&recent)
+            .style(Style::new().fg(Color::Cyan)),
+        sparkline_area,
+    );
+}
@@ -0,0 +1,97 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::PathBuf,
+    sync::atomic::Ordering,
+    time::Instant,
+};
+
+use anyhow::Result;
+use frame_analyzer::{Pid, export::SummaryRecord, stats::Window};
+
+use crate::{
+    feed::Feed,
+    output::{Format, Output},
+    parse::{self, parse_positive},
+    summary::AppSummary,
+    target::Targets,
+};
+
+#[derive(clap::Args, Debug)]
+pub struct Args {
+    #[command(flatten)]
+    targets: Targets,
+    /// Seconds between two summaries, also the window of the statistics
+    #[arg(short, long, value_parser = parse_positive, default_value_t = 1.0)]
+    interval: f64,
+    /// Receive the frames from a daemon at this socket instead of attaching the apps, needs no root
+    #[arg(short, long)]
+    daemon: Option<PathBuf>,
+    #[arg(short, long, value_enum, default_value_t)]
+    format: Format,
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let interval = parse::seconds(args.interval);
+    let pids = args.targets.resolve()?;
+    let mut feed = match &args.daemon {
+        Some(socket) => Feed::daemon(socket)?,
+        None => Feed::local()?,
+    };
+    for &pid in &pids {
+        feed.attach(pid)?;
+    }
+    let mut output = Output::<SummaryRecord>::new(args.format)?;
+    let running = crate::running()?;
+
+    let mut apps: BTreeMap<Pid, AppSummary> = pids
+        .iter()
+        .map(|&pid| (pid, AppSummary::new(pid, Window::Duration(interval))))
+        .collect();
+    let mut updated = HashSet::new();
+    let mut next = Instant::now() + interval;
+
+    while running.load(Ordering::Acquire) {
+        if let Some(event) = feed.recv_timeout(next.saturating_duration_since(Instant::now()))
+            && let Some(app) = apps.get_mut(&event.pid)
+        {
+            app.push(&event);
+            updated.insert(event.pid);
+        }
+
+        if Instant::now() >= next {
+            // an app without frames since the previous summary is idle, its window is stale
+            output.begin(true)?;
+            for (pid, app) in &apps {
+                if updated.contains(pid)
+                    && let Some(record) = app.record()
+                {
+                    output.write(&record)?;
+                }
+            }
+            output.flush()?;
+
+            updated.clear();
+            next = Instant::now() + interval;
+        }
+    }
+
+    Ok(())
+}
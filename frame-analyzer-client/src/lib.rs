@@ -0,0 +1,419 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+#![warn(clippy::nursery, clippy::all, clippy::pedantic)]
+#![allow(clippy::module_name_repetitions, clippy::cast_possible_truncation)]
+//! # frame-analyzer-client
+//!
+//! - Receive the frametimes of the apps attached by a frame-analyzer daemon, see `frame_analyzer::daemon`
+//! - Talks to the daemon over a unix socket, so it needs no privileges and does not link the ebpf loader
+//! - Reconnects by itself when the daemon restarts, the attached apps & subscriptions are restored
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use frame_analyzer_client::Client;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! # let app_pid = 1;
+//! let mut client = Client::connect("/dev/socket/frame-analyzer")?;
+//! client.attach_app(app_pid)?;
+//!
+//! while let Some((pid, frametime)) = client.recv() {
+//!     println!("process: {pid}, frametime: {frametime:?}");
+//! }
+//! #   Ok(())
+//! # }
+//! ```
+mod error;
+pub mod protocol;
+
+use std::{
+    collections::{HashSet, VecDeque},
+    io::{self, Read},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+pub use error::{ClientError, Result};
+use protocol::{Frame, Message, PROTOCOL_VERSION, Request};
+
+/// The pid of the target application
+pub type Pid = i32;
+
+/// How long to wait for the answer of the daemon to a request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Delay between two attempts to reconnect
+const RECONNECT_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A connection to a frame-analyzer daemon
+///
+/// Has the same receiving interface as `frame_analyzer::Analyzer`.
+/// If the connection is lost, the receiving methods reconnect and attach & subscribe again to what this client
+/// attached & subscribed, so a restart of the daemon only loses the frames sent while it was down
+///
+/// # Examples
+///
+/// ```
+/// use std::{os::unix::net::UnixListener, thread, time::Duration};
+///
+/// use frame_analyzer_client::{
+///     Client,
+///     protocol::{self, Frame, Message, PROTOCOL_VERSION, Request},
+/// };
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let path = std::env::temp_dir().join("frame-analyzer-client-doc.sock");
+/// let _ = std::fs::remove_file(&path);
+/// let listener = UnixListener::bind(&path)?;
+///
+/// // a tiny daemon: answers the attach request, then sends one frame
+/// let daemon = thread::spawn(move || -> std::io::Result<()> {
+///     let (mut stream, _) = listener.accept()?;
+///     protocol::write_message(&mut stream, &Message::Hello { version: PROTOCOL_VERSION })?;
+///     assert_eq!(protocol::read_request(&mut stream)?, Some(Request::Attach(42)));
+///     protocol::write_message(&mut stream, &Message::Ok)?;
+///     protocol::write_message(
+///         &mut stream,
+///         &Message::Frame(Frame {
+///             pid: 42,
+///             surface: 0x7f00,
+///             timestamp_ns: 16_000_000,
+///             frametime_ns: 16_000_000,
+///             refresh_period_ns: 0,
+///         }),
+///     )
+/// });
+///
+/// let mut client = Client::connect(&path)?;
+/// client.attach_app(42)?;
+/// assert_eq!(
+///     client.recv_timeout(Duration::from_secs(5)),
+///     Some((42, Duration::from_millis(16)))
+/// );
+/// daemon.join().unwrap()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Client {
+    path: PathBuf,
+    stream: Option<UnixStream>,
+    buffer: Vec<u8>,
+    frames: VecDeque<Frame>,
+    exited: Vec<Pid>,
+    attached: HashSet<Pid>,
+    subscribed: HashSet<Pid>,
+    all: bool,
+}
+
+impl Client {
+    /// Connect to the daemon listening at `path`
+    ///
+    /// # Errors
+    ///
+    /// - `IOError` if the socket cannot be connected
+    /// - `VersionMismatch` if the daemon speaks another protocol version
+    pub fn connect<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut client = Self {
+            path: path.as_ref().to_path_buf(),
+            stream: None,
+            buffer: Vec::new(),
+            frames: VecDeque::new(),
+            exited: Vec::new(),
+            attached: HashSet::new(),
+            subscribed: HashSet::new(),
+            all: false,
+        };
+        client.reconnect()?;
+
+        Ok(client)
+    }
+
+    /// Attach the daemon to an app and subscribe to its frames
+    ///
+    /// The app stays attached until this client detaches it or disconnects
+    ///
+    /// # Errors
+    ///
+    /// - `DaemonError` if the daemon failed to attach the app
+    /// - `IOError` / `Disconnected` if the daemon is unreachable
+    pub fn attach_app(&mut self, pid: Pid) -> Result<()> {
+        self.request(Request::Attach(pid))?;
+        self.attached.insert(pid);
+        self.subscribed.insert(pid);
+        Ok(())
+    }
+
+    /// Release an app attached by this client and unsubscribe from its frames
+    ///
+    /// # Errors
+    ///
+    /// - `DaemonError` if the daemon failed to detach the app
+    /// - `IOError` / `Disconnected` if the daemon is unreachable
+    pub fn detach_app(&mut self, pid: Pid) -> Result<()> {
+        self.request(Request::Detach(pid))?;
+        self.attached.remove(&pid);
+        self.subscribed.remove(&pid);
+        Ok(())
+    }
+
+    /// Receive the frames of an app attached by another client, `None` for every attached app
+    ///
+    /// # Errors
+    ///
+    /// `IOError` / `Disconnected` if the daemon is unreachable
+    pub fn subscribe(&mut self, pid: Option<Pid>) -> Result<()> {
+        self.request(Request::Subscribe(pid))?;
+        match pid {
+            Some(pid) => {
+                self.subscribed.insert(pid);
+            }
+            None => self.all = true,
+        }
+        Ok(())
+    }
+
+    /// Undo a [`Client::subscribe`]
+    ///
+    /// # Errors
+    ///
+    /// `IOError` / `Disconnected` if the daemon is unreachable
+    pub fn unsubscribe(&mut self, pid: Option<Pid>) -> Result<()> {
+        self.request(Request::Unsubscribe(pid))?;
+        match pid {
+            Some(pid) => {
+                self.subscribed.remove(&pid);
+            }
+            None => self.all = false,
+        }
+        Ok(())
+    }
+
+    /// Receive a frametime, blocks until one is available
+    ///
+    /// Waits for the daemon to come back if it is gone
+    pub fn recv(&mut self) -> Option<(Pid, Duration)> {
+        self.recv_frame()
+            .map(|frame| (frame.pid, frame.frametime()))
+    }
+
+    /// Receive a frametime, returning `None` if it waits more than timeout
+    pub fn recv_timeout(&mut self, time: Duration) -> Option<(Pid, Duration)> {
+        self.recv_frame_timeout(time)
+            .map(|frame| (frame.pid, frame.frametime()))
+    }
+
+    /// Like [`Client::recv`], but returns the whole [`Frame`]
+    pub fn recv_frame(&mut self) -> Option<Frame> {
+        self.poll_frame(None)
+    }
+
+    /// Like [`Client::recv_timeout`], but returns the whole [`Frame`]
+    pub fn recv_frame_timeout(&mut self, time: Duration) -> Option<Frame> {
+        self.poll_frame(Some(Instant::now() + time))
+    }
+
+    /// The subscribed apps which exited since the last call, they are neither attached nor subscribed anymore
+    pub fn take_exited(&mut self) -> Vec<Pid> {
+        std::mem::take(&mut self.exited)
+    }
+
+    /// Whether the connection to the daemon is up, it is restored by the next receive otherwise
+    #[must_use]
+    pub const fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    fn poll_frame(&mut self, deadline: Option<Instant>) -> Option<Frame> {
+        loop {
+            if let Some(frame) = self.frames.pop_front() {
+                return Some(frame);
+            }
+
+            if self.stream.is_none() && self.reconnect().is_err() {
+                let retry = Instant::now() + RECONNECT_INTERVAL;
+                let wake = deadline.map_or(retry, |deadline| deadline.min(retry));
+                thread::sleep(wake.saturating_duration_since(Instant::now()));
+            } else {
+                match self.read_message(deadline) {
+                    Ok(Some(Message::Frame(frame))) => return Some(frame),
+                    Ok(Some(Message::Exited(pid))) => self.on_exit(pid),
+                    Ok(_) => (),
+                    Err(_) => self.stream = None,
+                }
+            }
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return None;
+            }
+        }
+    }
+
+    /// Send a request and wait for its answer, queueing the frames received meanwhile
+    fn request(&mut self, request: Request) -> Result<()> {
+        if self.stream.is_none() {
+            self.reconnect()?;
+        }
+
+        let result = self.exchange(request);
+        if matches!(
+            result,
+            Err(ClientError::IOError(_) | ClientError::Disconnected)
+        ) {
+            self.stream = None;
+        }
+
+        result
+    }
+
+    fn exchange(&mut self, request: Request) -> Result<()> {
+        let stream = self.stream.as_mut().ok_or(ClientError::Disconnected)?;
+        protocol::write_request(stream, &request)?;
+
+        let deadline = Instant::now() + REQUEST_TIMEOUT;
+        loop {
+            match self.read_message(Some(deadline))? {
+                Some(Message::Ok) => return Ok(()),
+                Some(Message::Error(e)) => return Err(ClientError::DaemonError(e)),
+                Some(Message::Frame(frame)) => self.frames.push_back(frame),
+                Some(Message::Exited(pid)) => self.on_exit(pid),
+                Some(Message::Hello { .. }) => (),
+                None => return Err(io::Error::from(io::ErrorKind::TimedOut).into()),
+            }
+        }
+    }
+
+    /// Forget an app which exited, so it isn't attached again on reconnection
+    fn on_exit(&mut self, pid: Pid) {
+        self.attached.remove(&pid);
+        self.subscribed.remove(&pid);
+        self.exited.push(pid);
+    }
+
+    /// Connect again and restore the attachments & subscriptions of this client
+    fn reconnect(&mut self) -> Result<()> {
+        self.stream = Some(UnixStream::connect(&self.path)?);
+        self.buffer.clear();
+
+        match self.read_message(Some(Instant::now() + REQUEST_TIMEOUT)) {
+            Ok(Some(Message::Hello { version })) if version == PROTOCOL_VERSION => (),
+            Ok(Some(Message::Hello { version })) => {
+                self.stream = None;
+                return Err(ClientError::VersionMismatch(version));
+            }
+            result => {
+                self.stream = None;
+                result?;
+                return Err(ClientError::Disconnected);
+            }
+        }
+
+        let restored = self.restore();
+        if restored.is_err() {
+            self.stream = None;
+        }
+
+        restored
+    }
+
+    /// Attach & subscribe again on a new connection
+    fn restore(&mut self) -> Result<()> {
+        let attached: Vec<_> = self.attached.iter().copied().collect();
+        for pid in attached {
+            match self.exchange(Request::Attach(pid)) {
+                Ok(()) => (),
+                // the app may be gone meanwhile
+                Err(ClientError::DaemonError(_)) => {
+                    self.attached.remove(&pid);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        let subscribed: Vec<_> = self
+            .subscribed
+            .difference(&self.attached)
+            .copied()
+            .collect();
+        for pid in subscribed {
+            self.exchange(Request::Subscribe(Some(pid)))?;
+        }
+        if self.all {
+            self.exchange(Request::Subscribe(None))?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the next message, `Ok(None)` if none arrived before the deadline
+    fn read_message(&mut self, deadline: Option<Instant>) -> Result<Option<Message>> {
+        loop {
+            if let Some(message) = self.take_message()? {
+                return Ok(Some(message));
+            }
+
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Ok(None);
+                    }
+                    Some(remaining)
+                }
+                None => None,
+            };
+
+            let stream = self.stream.as_mut().ok_or(ClientError::Disconnected)?;
+            stream.set_read_timeout(timeout)?;
+            let mut chunk = [0; 4096];
+            match stream.read(&mut chunk) {
+                Ok(0) => return Err(ClientError::Disconnected),
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock
+                            | io::ErrorKind::TimedOut
+                            | io::ErrorKind::Interrupted
+                    ) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Decode a message if a whole frame is buffered
+    fn take_message(&mut self) -> Result<Option<Message>> {
+        let Some(len) = self.buffer.first_chunk::<4>() else {
+            return Ok(None);
+        };
+        let len = u32::from_le_bytes(*len) as usize;
+        if len > protocol::MAX_PAYLOAD_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too long").into());
+        }
+
+        let end = 4 + len;
+        if self.buffer.len() < end {
+            return Ok(None);
+        }
+
+        let message = protocol::read_message(&mut &self.buffer[..end])?;
+        self.buffer.drain(..end);
+        Ok(message)
+    }
+}
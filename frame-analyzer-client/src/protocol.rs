@@ -0,0 +1,304 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! The wire protocol between the daemon and its clients
+//!
+//! Every message is a frame: payload length `u32`, then the payload, a tag `u8` followed by the fields.
+//! All integers are little-endian.
+//! The daemon greets every client with [`Message::Hello`], answers every [`Request`] in order with
+//! [`Message::Ok`] or [`Message::Error`], and streams [`Message::Frame`]s of the subscribed pids in between,
+//! followed by a [`Message::Exited`] once such a pid exits
+//!
+//! # Examples
+//!
+//! ```
+//! use frame_analyzer_client::protocol::{self, Request};
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let mut wire = Vec::new();
+//! protocol::write_request(&mut wire, &Request::Attach(42))?;
+//! protocol::write_request(&mut wire, &Request::Subscribe(None))?;
+//!
+//! let mut reader = wire.as_slice();
+//! assert_eq!(protocol::read_request(&mut reader)?, Some(Request::Attach(42)));
+//! assert_eq!(protocol::read_request(&mut reader)?, Some(Request::Subscribe(None)));
+//! assert_eq!(protocol::read_request(&mut reader)?, None);
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+/// Version of the protocol, sent in [`Message::Hello`]
+pub const PROTOCOL_VERSION: u16 = 2;
+/// Longest accepted payload
+pub const MAX_PAYLOAD_LEN: usize = 4096;
+
+const TAG_ATTACH: u8 = 0x01;
+const TAG_DETACH: u8 = 0x02;
+const TAG_SUBSCRIBE: u8 = 0x03;
+const TAG_UNSUBSCRIBE: u8 = 0x04;
+const TAG_HELLO: u8 = 0x80;
+const TAG_FRAME: u8 = 0x81;
+const TAG_OK: u8 = 0x82;
+const TAG_ERROR: u8 = 0x83;
+const TAG_EXITED: u8 = 0x84;
+
+/// A request of a client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    /// Attach the analyzer to an app and subscribe to its frames, the daemon keeps it attached until every client attaching it detaches
+    Attach(i32),
+    /// Release an attachment of this client and unsubscribe from its frames
+    Detach(i32),
+    /// Receive the frames of a pid attached by any client, `None` for every attached pid
+    Subscribe(Option<i32>),
+    /// Undo a [`Request::Subscribe`]
+    Unsubscribe(Option<i32>),
+}
+
+/// A frame of an attached app
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// The pid of the application
+    pub pid: i32,
+    /// The surface the frame was queued to
+    pub surface: u64,
+    /// When the frame was queued, in nanoseconds of `CLOCK_MONOTONIC`
+    pub timestamp_ns: u64,
+    /// Time since the previous frame of the same surface
+    pub frametime_ns: u64,
+    /// The display refresh period, `0` if it is unknown
+    pub refresh_period_ns: u64,
+}
+
+impl Frame {
+    /// Time since the previous frame of the same surface
+    #[must_use]
+    pub const fn frametime(&self) -> Duration {
+        Duration::from_nanos(self.frametime_ns)
+    }
+
+    /// The display refresh period, if it is known
+    #[must_use]
+    pub const fn refresh_period(&self) -> Option<Duration> {
+        if self.refresh_period_ns == 0 {
+            None
+        } else {
+            Some(Duration::from_nanos(self.refresh_period_ns))
+        }
+    }
+}
+
+/// A message of the daemon
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// Sent once when the client connects
+    Hello {
+        /// [`PROTOCOL_VERSION`] of the daemon
+        version: u16,
+    },
+    /// A frame of a subscribed pid
+    Frame(Frame),
+    /// The request succeeded
+    Ok,
+    /// The request failed
+    Error(String),
+    /// A subscribed pid exited, the daemon detached it and dropped every attachment & subscription of it
+    Exited(i32),
+}
+
+/// Write a request as a frame
+///
+/// # Errors
+///
+/// Any error of the writer
+pub fn write_request<W: Write>(writer: &mut W, request: &Request) -> io::Result<()> {
+    let mut payload = Vec::with_capacity(6);
+    match *request {
+        Request::Attach(pid) => {
+            payload.push(TAG_ATTACH);
+            payload.extend_from_slice(&pid.to_le_bytes());
+        }
+        Request::Detach(pid) => {
+            payload.push(TAG_DETACH);
+            payload.extend_from_slice(&pid.to_le_bytes());
+        }
+        Request::Subscribe(pid) => {
+            payload.push(TAG_SUBSCRIBE);
+            put_optional_pid(&mut payload, pid);
+        }
+        Request::Unsubscribe(pid) => {
+            payload.push(TAG_UNSUBSCRIBE);
+            put_optional_pid(&mut payload, pid);
+        }
+    }
+
+    write_frame(writer, &payload)
+}
+
+/// Read a request, `Ok(None)` if the connection is closed
+///
+/// # Errors
+///
+/// - Any error of the reader
+/// - `InvalidData` if the frame is not a valid request
+pub fn read_request<R: Read>(reader: &mut R) -> io::Result<Option<Request>> {
+    let Some(payload) = read_frame(reader)? else {
+        return Ok(None);
+    };
+    let (&tag, mut fields) = payload
+        .split_first()
+        .ok_or_else(|| invalid("empty frame"))?;
+
+    let request = match tag {
+        TAG_ATTACH => Request::Attach(i32::from_le_bytes(take(&mut fields)?)),
+        TAG_DETACH => Request::Detach(i32::from_le_bytes(take(&mut fields)?)),
+        TAG_SUBSCRIBE => Request::Subscribe(take_optional_pid(&mut fields)?),
+        TAG_UNSUBSCRIBE => Request::Unsubscribe(take_optional_pid(&mut fields)?),
+        _ => return Err(invalid("unknown request")),
+    };
+
+    Ok(Some(request))
+}
+
+/// Write a message as a frame
+///
+/// # Errors
+///
+/// Any error of the writer
+pub fn write_message<W: Write>(writer: &mut W, message: &Message) -> io::Result<()> {
+    let mut payload = Vec::with_capacity(37);
+    match message {
+        Message::Hello { version } => {
+            payload.push(TAG_HELLO);
+            payload.extend_from_slice(&version.to_le_bytes());
+        }
+        Message::Frame(frame) => {
+            payload.push(TAG_FRAME);
+            payload.extend_from_slice(&frame.pid.to_le_bytes());
+            payload.extend_from_slice(&frame.surface.to_le_bytes());
+            payload.extend_from_slice(&frame.timestamp_ns.to_le_bytes());
+            payload.extend_from_slice(&frame.frametime_ns.to_le_bytes());
+            payload.extend_from_slice(&frame.refresh_period_ns.to_le_bytes());
+        }
+        Message::Ok => payload.push(TAG_OK),
+        Message::Error(error) => {
+            payload.push(TAG_ERROR);
+            let error = &error.as_bytes()[..error.len().min(MAX_PAYLOAD_LEN - 1)];
+            payload.extend_from_slice(error);
+        }
+        Message::Exited(pid) => {
+            payload.push(TAG_EXITED);
+            payload.extend_from_slice(&pid.to_le_bytes());
+        }
+    }
+
+    write_frame(writer, &payload)
+}
+
+/// Read a message, `Ok(None)` if the connection is closed
+///
+/// # Errors
+///
+/// - Any error of the reader
+/// - `InvalidData` if the frame is not a valid message
+pub fn read_message<R: Read>(reader: &mut R) -> io::Result<Option<Message>> {
+    let Some(payload) = read_frame(reader)? else {
+        return Ok(None);
+    };
+    let (&tag, mut fields) = payload
+        .split_first()
+        .ok_or_else(|| invalid("empty frame"))?;
+
+    let message = match tag {
+        TAG_HELLO => Message::Hello {
+            version: u16::from_le_bytes(take(&mut fields)?),
+        },
+        TAG_FRAME => Message::Frame(Frame {
+            pid: i32::from_le_bytes(take(&mut fields)?),
+            surface: u64::from_le_bytes(take(&mut fields)?),
+            timestamp_ns: u64::from_le_bytes(take(&mut fields)?),
+            frametime_ns: u64::from_le_bytes(take(&mut fields)?),
+            refresh_period_ns: u64::from_le_bytes(take(&mut fields)?),
+        }),
+        TAG_OK => Message::Ok,
+        TAG_ERROR => Message::Error(String::from_utf8_lossy(fields).into_owned()),
+        TAG_EXITED => Message::Exited(i32::from_le_bytes(take(&mut fields)?)),
+        _ => return Err(invalid("unknown message")),
+    };
+
+    Ok(Some(message))
+}
+
+fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)
+}
+
+fn read_frame<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_PAYLOAD_LEN {
+        return Err(invalid("frame too long"));
+    }
+
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload)?;
+    Ok(Some(payload))
+}
+
+fn put_optional_pid(payload: &mut Vec<u8>, pid: Option<i32>) {
+    if let Some(pid) = pid {
+        payload.push(1);
+        payload.extend_from_slice(&pid.to_le_bytes());
+    } else {
+        payload.push(0);
+    }
+}
+
+fn take_optional_pid(fields: &mut &[u8]) -> io::Result<Option<i32>> {
+    match take::<1>(fields)? {
+        [0] => Ok(None),
+        _ => Ok(Some(i32::from_le_bytes(take(fields)?))),
+    }
+}
+
+fn take<const N: usize>(fields: &mut &[u8]) -> io::Result<[u8; N]> {
+    if fields.len() < N {
+        return Err(invalid("truncated frame"));
+    }
+
+    let (head, tail) = fields.split_at(N);
+    *fields = tail;
+    Ok(head.try_into().unwrap_or([0; N]))
+}
+
+fn invalid(error: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
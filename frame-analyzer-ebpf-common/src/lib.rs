@@ -18,14 +18,158 @@
  */
 #![no_std]
 
+/// Index of the flags word in the `CONFIG` array map
+pub const CONFIG_FLAGS: u32 = 0;
+/// Compute the frametime in the ebpf program and report [`FrametimeRecord`]s instead of [`TimestampRecord`]s
+pub const FLAG_KERNEL_FRAMETIME: u32 = 1 << 0;
+/// Drop the signals of the surfaces which are not the one selected by userspace in `SELECTED_SURFACE`
+pub const FLAG_SUPPRESS_UNSELECTED: u32 = 1 << 1;
+/// Bin the frametimes into `HISTOGRAM` instead of reporting them through the ring buffer
+pub const FLAG_HISTOGRAM: u32 = 1 << 2;
+/// Stop suppressing the other surfaces once the selected one has been idle for this long
+pub const SUPPRESS_TIMEOUT_NS: u64 = 500_000_000;
+
+/// Number of 1ms wide bins, covering `[0ms, 32ms)`
+pub const HISTOGRAM_LINEAR_BINS: usize = 32;
+/// Width of the linear bins
+pub const HISTOGRAM_LINEAR_WIDTH_NS: u64 = 1_000_000;
+/// Total number of bins, the bins after the linear ones double in width: `[32ms, 64ms)`, `[64ms, 128ms)`...
+/// and the last one is unbounded
+pub const HISTOGRAM_BINS: usize = 40;
+
+/// Ring buffer record of a frame, without [`FLAG_KERNEL_FRAMETIME`] userspace subtracts the timestamps of a surface
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TimestampRecord {
+    pub ktime_ns: u64,
+    pub buffer: usize,
+    pub pid: u32,
+    // explicit padding, the verifier rejects records with uninitialized bytes
+    padding: u32,
+}
+
+impl TimestampRecord {
+    pub const fn new(ktime_ns: u64, buffer: usize, pid: u32) -> Self {
+        Self {
+            ktime_ns,
+            buffer,
+            pid,
+            padding: 0,
+        }
+    }
+}
+
+/// Ring buffer record of a frame with [`FLAG_KERNEL_FRAMETIME`], two thirds of a [`TimestampRecord`]
+///
+/// It has no timestamp, userspace adds the frametimes of a surface up instead.
+/// The first frame of a surface has no frametime and is not reported
 #[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FrametimeRecord {
+    pub buffer: usize,
+    /// Time since the previous frame of the surface, saturated at `u32::MAX` (about 4.3s)
+    pub frametime_ns: u32,
+    pub pid: u32,
+}
+
+impl FrametimeRecord {
+    pub const fn new(buffer: usize, frametime_ns: u64, pid: u32) -> Self {
+        Self {
+            buffer,
+            frametime_ns: if frametime_ns > u32::MAX as u64 {
+                u32::MAX
+            } else {
+                frametime_ns as u32
+            },
+            pid,
+        }
+    }
+}
+
+/// A frame queued by an application, as decoded from the ring buffer records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct FrameSignal {
+    /// When the frame was queued, in nanoseconds of `CLOCK_MONOTONIC`
     pub ktime_ns: u64,
+    /// The surface the frame was queued to
     pub buffer: usize,
+    /// Time since the previous frame of the same surface, `0` if it is not computed by the ebpf program
+    pub frametime_ns: u64,
+    /// The pid (tgid) of the application
+    pub pid: u32,
 }
 
 impl FrameSignal {
-    pub const fn new(ktime_ns: u64, buffer: usize) -> Self {
-        Self { ktime_ns, buffer }
+    pub const fn new(ktime_ns: u64, buffer: usize, frametime_ns: u64, pid: u32) -> Self {
+        Self {
+            ktime_ns,
+            buffer,
+            frametime_ns,
+            pid,
+        }
+    }
+}
+
+/// Frametime histogram of an app, see [`histogram_bin`] for the layout of the bins
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FrameHistogram {
+    pub bins: [u64; HISTOGRAM_BINS],
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for FrameHistogram {}
+
+impl FrameHistogram {
+    pub const fn new() -> Self {
+        Self {
+            bins: [0; HISTOGRAM_BINS],
+        }
+    }
+}
+
+impl Default for FrameHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The bin of `frametime_ns`, linear up to 32ms and log2 after that
+pub const fn histogram_bin(frametime_ns: u64) -> usize {
+    let ms = frametime_ns / HISTOGRAM_LINEAR_WIDTH_NS;
+    if ms < HISTOGRAM_LINEAR_BINS as u64 {
+        return ms as usize;
+    }
+
+    // ms >= 32 here, so log2 >= 5
+    let log2 = (u64::BITS - 1 - ms.leading_zeros()) as usize;
+    let bin = HISTOGRAM_LINEAR_BINS + log2 - 5;
+    if bin < HISTOGRAM_BINS {
+        bin
+    } else {
+        HISTOGRAM_BINS - 1
+    }
+}
+
+/// Key of the per-surface maps of the ebpf program
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SurfaceKey {
+    pub pid: u32,
+    // explicit padding, the verifier rejects map keys with uninitialized bytes
+    padding: u32,
+    pub buffer: usize,
+}
+
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for SurfaceKey {}
+
+impl SurfaceKey {
+    pub const fn new(pid: u32, buffer: usize) -> Self {
+        Self {
+            pid,
+            padding: 0,
+            buffer,
+        }
     }
 }
@@ -20,17 +20,39 @@
 #![no_main]
 
 use aya_ebpf::{
-    helpers::bpf_ktime_get_ns,
+    helpers::{bpf_get_current_pid_tgid, bpf_ktime_get_ns},
     macros::{map, uprobe},
-    maps::RingBuf,
+    maps::{Array, HashMap, LruHashMap, PerCpuHashMap, RingBuf},
     programs::ProbeContext,
 };
 
-use frame_analyzer_ebpf_common::FrameSignal;
+use frame_analyzer_ebpf_common::{
+    CONFIG_FLAGS, FLAG_HISTOGRAM, FLAG_KERNEL_FRAMETIME, FLAG_SUPPRESS_UNSELECTED, FrameHistogram,
+    FrametimeRecord, SUPPRESS_TIMEOUT_NS, SurfaceKey, TimestampRecord, histogram_bin,
+};
 
 #[map]
 static RING_BUF: RingBuf = RingBuf::with_byte_size(0x1000, 0);
 
+#[map]
+static PID_FILTER: HashMap<u32, u8> = HashMap::with_max_entries(1024, 0);
+
+#[map]
+static CONFIG: Array<u32> = Array::with_max_entries(1, 0);
+
+#[map]
+static LAST_FRAME: LruHashMap<SurfaceKey, u64> = LruHashMap::with_max_entries(1024, 0);
+
+#[map]
+static SELECTED_SURFACE: HashMap<u32, u64> = HashMap::with_max_entries(1024, 0);
+
+#[map]
+static HISTOGRAM: PerCpuHashMap<u32, FrameHistogram> = PerCpuHashMap::with_max_entries(1024, 0);
+
+/// Signals lost because the ring buffer was full, per pid
+#[map]
+static DROPPED: PerCpuHashMap<u32, u64> = PerCpuHashMap::with_max_entries(1024, 0);
+
 #[uprobe]
 pub fn frame_analyzer_ebpf(ctx: ProbeContext) -> u32 {
     match try_frame_analyzer_ebpf(ctx) {
@@ -39,16 +61,113 @@ pub fn frame_analyzer_ebpf(ctx: ProbeContext) -> u32 {
     }
 }
 
+#[uprobe]
+pub fn frame_analyzer_ebpf_global(ctx: ProbeContext) -> u32 {
+    match try_frame_analyzer_ebpf_global(ctx) {
+        Ok(ret) => ret,
+        Err(ret) => ret,
+    }
+}
+
 fn try_frame_analyzer_ebpf(ctx: ProbeContext) -> Result<u32, u32> {
-    if let Some(mut entry) = RING_BUF.reserve::<FrameSignal>(0) {
-        let ktime_ns = unsafe { bpf_ktime_get_ns() };
-        entry.write(FrameSignal::new(ktime_ns, ctx.arg::<usize>(0).unwrap()));
-        entry.submit(0);
+    submit_signal(&ctx, current_pid())
+}
+
+fn try_frame_analyzer_ebpf_global(ctx: ProbeContext) -> Result<u32, u32> {
+    let pid = current_pid();
+
+    // attached to every process, only report the ones userspace asked for
+    if unsafe { PID_FILTER.get(&pid) }.is_none() {
+        return Ok(0);
     }
 
+    submit_signal(&ctx, pid)
+}
+
+fn submit_signal(ctx: &ProbeContext, pid: u32) -> Result<u32, u32> {
+    let ktime_ns = unsafe { bpf_ktime_get_ns() };
+    let buffer = ctx.arg::<usize>(0).unwrap();
+    let flags = CONFIG.get(CONFIG_FLAGS).copied().unwrap_or_default();
+
+    if flags & FLAG_KERNEL_FRAMETIME == 0 {
+        submit(pid, TimestampRecord::new(ktime_ns, buffer, pid));
+        return Ok(0);
+    }
+
+    let key = SurfaceKey::new(pid, buffer);
+    let last = unsafe { LAST_FRAME.get(&key) }.copied();
+    let _ = LAST_FRAME.insert(&key, &ktime_ns, 0);
+    // the first frame of a surface has no frametime, there is nothing to report
+    let Some(last) = last else {
+        return Ok(0);
+    };
+    let frametime_ns = ktime_ns.saturating_sub(last);
+
+    if flags & FLAG_SUPPRESS_UNSELECTED != 0 && is_suppressed(pid, buffer, ktime_ns) {
+        return Ok(0);
+    }
+
+    if flags & FLAG_HISTOGRAM != 0 {
+        record_histogram(pid, frametime_ns);
+        return Ok(0);
+    }
+
+    submit(pid, FrametimeRecord::new(buffer, frametime_ns, pid));
     Ok(0)
 }
 
+fn submit<T: 'static>(pid: u32, record: T) {
+    if let Some(mut entry) = RING_BUF.reserve::<T>(0) {
+        entry.write(record);
+        entry.submit(0);
+    } else {
+        record_dropped(pid);
+    }
+}
+
+fn is_suppressed(pid: u32, buffer: usize, ktime_ns: u64) -> bool {
+    let Some(selected) = (unsafe { SELECTED_SURFACE.get(&pid) }) else {
+        return false;
+    };
+
+    if *selected == buffer as u64 {
+        return false;
+    }
+
+    // keep suppressing only while the selected surface is alive, so a surface switch can still be noticed
+    let selected_key = SurfaceKey::new(pid, *selected as usize);
+    unsafe { LAST_FRAME.get(&selected_key) }
+        .is_some_and(|last| ktime_ns.saturating_sub(*last) < SUPPRESS_TIMEOUT_NS)
+}
+
+fn record_histogram(pid: u32, frametime_ns: u64) {
+    let bin = histogram_bin(frametime_ns);
+
+    if let Some(histogram) = HISTOGRAM.get_ptr_mut(&pid) {
+        unsafe {
+            (*histogram).bins[bin] += 1;
+        }
+    } else {
+        let mut histogram = FrameHistogram::new();
+        histogram.bins[bin] = 1;
+        let _ = HISTOGRAM.insert(&pid, &histogram, 0);
+    }
+}
+
+fn record_dropped(pid: u32) {
+    if let Some(dropped) = DROPPED.get_ptr_mut(&pid) {
+        unsafe {
+            *dropped += 1;
+        }
+    } else {
+        let _ = DROPPED.insert(&pid, &1, 0);
+    }
+}
+
+fn current_pid() -> u32 {
+    (bpf_get_current_pid_tgid() >> 32) as u32
+}
+
 #[panic_handler]
 fn panic(_info: &core::panic::PanicInfo) -> ! {
     unsafe { core::hint::unreachable_unchecked() }
@@ -0,0 +1,72 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Measure how many frame signals per second the `Analyzer` processes
+//!
+//! Run with `cargo bench -p frame-analyzer --bench throughput`, no privilege is needed
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use frame_analyzer::{
+    Analyzer,
+    source::MemorySource,
+    synthetic::{self, Jitter, Scenario},
+};
+
+const APPS: i32 = 4;
+const DURATION: Duration = Duration::from_secs(600);
+
+fn main() -> Result<()> {
+    let scenarios: Vec<_> = (0..APPS)
+        .map(|pid| {
+            Scenario::new(pid + 1, 120.0)
+                .duration(DURATION)
+                .jitter(Jitter::Normal(Duration::from_millis(1)))
+                .hitch(100, Duration::from_millis(30))
+                .extra_surface(0x1000, 30.0)
+                .seed(pid as u64)
+        })
+        .collect();
+
+    let generating = Instant::now();
+    let signals = synthetic::signals(&scenarios);
+    let generated = generating.elapsed();
+
+    let count = signals.len();
+    let mut analyzer = Analyzer::with_source(MemorySource::from_signals(signals)?)?;
+    for pid in 1..=APPS {
+        analyzer.attach_app(pid)?;
+    }
+
+    let analyzing = Instant::now();
+    let mut events = 0usize;
+    while !analyzer.is_exhausted() {
+        if analyzer.recv_event().is_some() {
+            events += 1;
+        }
+    }
+    let analyzed = analyzing.elapsed();
+
+    println!("generated {count} signals in {generated:?}");
+    println!(
+        "analyzed {count} signals ({events} frame events) in {analyzed:?}: {:.0} signals/s",
+        count as f64 / analyzed.as_secs_f64()
+    );
+
+    Ok(())
+}
@@ -18,33 +18,30 @@
  */
 use std::{
     collections::{HashMap, VecDeque},
-    ptr,
     time::Duration,
 };
 
 use frame_analyzer_ebpf_common::FrameSignal;
 
-use crate::uprobe::UprobeHandler;
-
 pub struct AnalyzeTarget {
-    pub uprobe: UprobeHandler,
     buffers: HashMap<usize, (u64, VecDeque<Duration>)>,
+    selected: Option<usize>,
 }
 
 impl AnalyzeTarget {
-    pub fn new(uprobe: UprobeHandler) -> Self {
+    pub fn new() -> Self {
         Self {
-            uprobe,
             buffers: HashMap::new(),
+            selected: None,
         }
     }
 
-    pub fn update(&mut self) -> Option<Duration> {
-        let mut ring = self.uprobe.ring().unwrap();
-        let item = ring.next()?;
-        let event = unsafe { trans(&item) };
+    pub fn update(&mut self, event: &FrameSignal) -> Option<Duration> {
         if let Some((timestamp, buffer)) = self.buffers.get_mut(&event.buffer) {
-            let frametime = event.ktime_ns.saturating_sub(*timestamp);
+            let frametime = match event.frametime_ns {
+                0 => event.ktime_ns.saturating_sub(*timestamp),
+                frametime => frametime,
+            };
             *timestamp = event.ktime_ns;
 
             if buffer.len() >= 144 {
@@ -53,8 +50,12 @@ impl AnalyzeTarget {
 
             buffer.push_front(Duration::from_nanos(frametime));
         } else {
-            self.buffers
-                .insert(event.buffer, (event.ktime_ns, VecDeque::with_capacity(144)));
+            // the frametime of the first frame is only known if the ebpf program computed it
+            let mut buffer = VecDeque::with_capacity(144);
+            if event.frametime_ns != 0 {
+                buffer.push_front(Duration::from_nanos(event.frametime_ns));
+            }
+            self.buffers.insert(event.buffer, (event.ktime_ns, buffer));
         }
 
         let max_len = self
@@ -70,13 +71,15 @@ impl AnalyzeTarget {
                 .filter(|(_, buffer)| buffer.len() == max_len)
                 .min_by_key(|(_, buffer)| buffer.iter().copied().sum::<Duration>())
         {
+            self.selected = Some(event.buffer);
             self.buffers.get(&event.buffer)?.1.front().copied()
         } else {
             None
         }
     }
-}
 
-const unsafe fn trans(buf: &[u8]) -> FrameSignal {
-    unsafe { ptr::read_unaligned(buf.as_ptr().cast::<FrameSignal>()) }
+    /// The surface whose frametimes are reported
+    pub const fn selected(&self) -> Option<usize> {
+        self.selected
+    }
 }
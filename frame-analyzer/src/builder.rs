@@ -0,0 +1,158 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use frame_analyzer_ebpf_common::{FLAG_HISTOGRAM, FLAG_KERNEL_FRAMETIME, FLAG_SUPPRESS_UNSELECTED};
+
+use crate::{Analyzer, error::Result};
+
+/// The way the [`Analyzer`] hooks the target applications
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Load a dedicated ebpf program for every attached application
+    ///
+    /// Attaching or detaching an application loads or unloads a whole ebpf program
+    #[default]
+    PerApp,
+    /// Load a single ebpf program probing every process, filtered by a kernel-side pid map
+    ///
+    /// Attaching or detaching an application is just a map update, the program is loaded on the first attach
+    Global,
+}
+
+/// Where the frametime is computed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameMode {
+    /// The ebpf program reports raw timestamps and the frametime is computed in userspace
+    #[default]
+    Userspace,
+    /// The ebpf program keeps the last timestamp of every surface and reports the frametime directly
+    ///
+    /// The records are a third smaller than the timestamps of [`FrameMode::Userspace`],
+    /// and the first frame of a surface, which has no frametime yet, is not reported at all
+    Kernel {
+        /// Drop the frames of the surfaces not selected by the [`Analyzer`] in the ebpf program
+        ///
+        /// This saves ring buffer traffic & userspace wakeups for apps drawing multiple surfaces,
+        /// other surfaces are only reported again after the selected one stops drawing for a while
+        suppress_unselected: bool,
+    },
+    /// The ebpf program bins the frametimes into a per-app histogram and reports nothing through the ring buffer
+    ///
+    /// There is no wakeup per frame, [`Analyzer::recv`] never returns any frame in this mode,
+    /// read the histograms periodically with [`Analyzer::snapshot_histogram`] instead.
+    /// The frames of every surface of the app are counted
+    Histogram,
+}
+
+impl FrameMode {
+    pub(crate) const fn flags(self) -> u32 {
+        match self {
+            Self::Userspace => 0,
+            Self::Kernel {
+                suppress_unselected: false,
+            } => FLAG_KERNEL_FRAMETIME,
+            Self::Kernel {
+                suppress_unselected: true,
+            } => FLAG_KERNEL_FRAMETIME | FLAG_SUPPRESS_UNSELECTED,
+            Self::Histogram => FLAG_KERNEL_FRAMETIME | FLAG_HISTOGRAM,
+        }
+    }
+
+    pub(crate) const fn suppress_unselected(self) -> bool {
+        matches!(
+            self,
+            Self::Kernel {
+                suppress_unselected: true
+            }
+        )
+    }
+}
+
+/// Builder of [`Analyzer`]
+///
+/// # Examples
+///
+/// ```
+/// use frame_analyzer::{Analyzer, Backend};
+///
+/// # fn main() {
+/// #   let _ = try_main();
+/// # }
+/// #
+/// # fn try_main() -> anyhow::Result<()> {
+/// #   let app_pid = 1;
+/// let mut analyzer = Analyzer::builder().backend(Backend::Global).build()?;
+/// analyzer.attach_app(app_pid)?;
+/// #   Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AnalyzerBuilder {
+    pub(crate) backend: Backend,
+    pub(crate) frame_mode: FrameMode,
+}
+
+impl AnalyzerBuilder {
+    /// Create a builder with the default settings
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Select the [`Backend`] used to hook the target applications, [`Backend::PerApp`] by default
+    #[must_use]
+    pub const fn backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Select where the frametime is computed, [`FrameMode::Userspace`] by default
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use frame_analyzer::{Analyzer, FrameMode};
+    ///
+    /// # fn main() {
+    /// #   let _ = try_main();
+    /// # }
+    /// #
+    /// # fn try_main() -> anyhow::Result<()> {
+    /// let analyzer = Analyzer::builder()
+    ///     .frame_mode(FrameMode::Kernel {
+    ///         suppress_unselected: true,
+    ///     })
+    ///     .build()?;
+    /// #   Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub const fn frame_mode(mut self, frame_mode: FrameMode) -> Self {
+        self.frame_mode = frame_mode;
+        self
+    }
+
+    /// Build the [`Analyzer`]
+    ///
+    /// # Errors
+    ///
+    /// See [`Analyzer::new`]
+    pub fn build(self) -> Result<Analyzer> {
+        Analyzer::from_builder(&self)
+    }
+}
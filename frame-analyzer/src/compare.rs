@@ -0,0 +1,463 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Compare two sessions, e.g. before & after a governor change
+//!
+//! A [`Comparison`] holds the [`Delta`]s of the fps, the percentile frametimes and the jank rates
+//! between a base and a new session. Given the frametimes of both it also runs a [`MannWhitney`] test,
+//! so that noise between runs is not reported as a regression
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use frame_analyzer::{
+//!     compare::{Comparison, Metric, Thresholds},
+//!     jank::JankCounts,
+//! };
+//!
+//! // one frame in ten takes two vsyncs after the change
+//! let base = vec![Duration::from_micros(16_667); 600];
+//! let mut new = base.clone();
+//! for frametime in new.iter_mut().step_by(10) {
+//!     *frametime = Duration::from_micros(33_333);
+//! }
+//! let base_janks = JankCounts { frames: 600, jank: 0, big_jank: 0 };
+//! let new_janks = JankCounts { frames: 600, jank: 60, big_jank: 0 };
+//!
+//! let comparison = Comparison::from_frametimes(&base, base_janks, &new, new_janks).unwrap();
+//! assert!(comparison.delta(Metric::AverageFps).percent().unwrap() < -5.0);
+//! assert_eq!(comparison.delta(Metric::JankRate).change(), 10.0);
+//! assert!(comparison.significance.unwrap().p_value < 0.001);
+//!
+//! let regressions = comparison.regressions(&Thresholds::default());
+//! assert!(regressions.iter().any(|delta| delta.metric == Metric::AverageFps));
+//! assert!(regressions.iter().any(|delta| delta.metric == Metric::JankRate));
+//! // the median frame did not change
+//! assert!(regressions.iter().all(|delta| delta.metric != Metric::Median));
+//! ```
+use std::{f64::consts::SQRT_2, time::Duration};
+
+use crate::{
+    jank::JankCounts,
+    stats::{FrameStats, StatsSummary, Window},
+};
+
+/// The statistics of one session
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sample {
+    /// Statistics over the frames of the session
+    pub summary: StatsSummary,
+    /// Janks over the frames of the session
+    pub janks: JankCounts,
+}
+
+impl Sample {
+    #[must_use]
+    pub const fn new(summary: StatsSummary, janks: JankCounts) -> Self {
+        Self { summary, janks }
+    }
+
+    /// Percentage of janky frames, including the big janks, 0 without frames
+    #[must_use]
+    pub fn jank_rate(&self) -> f64 {
+        rate(self.janks.janky(), self.janks.frames)
+    }
+
+    /// Percentage of big jank frames, 0 without frames
+    #[must_use]
+    pub fn big_jank_rate(&self) -> f64 {
+        rate(self.janks.big_jank, self.janks.frames)
+    }
+}
+
+/// A compared statistic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Metric {
+    /// Average fps, see [`StatsSummary::average_fps`]
+    AverageFps,
+    /// See [`StatsSummary::low_1_percent_fps`]
+    Low1PercentFps,
+    /// See [`StatsSummary::low_0_1_percent_fps`]
+    Low01PercentFps,
+    /// The median frametime, in milliseconds
+    Median,
+    /// The 90th percentile frametime, in milliseconds
+    P90,
+    /// The 95th percentile frametime, in milliseconds
+    P95,
+    /// The 99th percentile frametime, in milliseconds
+    P99,
+    /// See [`Sample::jank_rate`]
+    JankRate,
+    /// See [`Sample::big_jank_rate`]
+    BigJankRate,
+}
+
+impl Metric {
+    /// Every metric, in the order of [`Comparison::deltas`]
+    pub const ALL: [Self; 9] = [
+        Self::AverageFps,
+        Self::Low1PercentFps,
+        Self::Low01PercentFps,
+        Self::Median,
+        Self::P90,
+        Self::P95,
+        Self::P99,
+        Self::JankRate,
+        Self::BigJankRate,
+    ];
+
+    /// A human readable name
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::AverageFps => "average fps",
+            Self::Low1PercentFps => "1% low fps",
+            Self::Low01PercentFps => "0.1% low fps",
+            Self::Median => "median frametime",
+            Self::P90 => "p90 frametime",
+            Self::P95 => "p95 frametime",
+            Self::P99 => "p99 frametime",
+            Self::JankRate => "jank rate",
+            Self::BigJankRate => "big jank rate",
+        }
+    }
+
+    /// The unit of the values, `fps`, `ms` or `%`
+    #[must_use]
+    pub const fn unit(self) -> &'static str {
+        match self {
+            Self::AverageFps | Self::Low1PercentFps | Self::Low01PercentFps => "fps",
+            Self::Median | Self::P90 | Self::P95 | Self::P99 => "ms",
+            Self::JankRate | Self::BigJankRate => "%",
+        }
+    }
+
+    /// Whether an increase is an improvement
+    #[must_use]
+    pub const fn higher_is_better(self) -> bool {
+        matches!(
+            self,
+            Self::AverageFps | Self::Low1PercentFps | Self::Low01PercentFps
+        )
+    }
+
+    /// Whether the metric is a percentage, compared in percentage points rather than relatively
+    #[must_use]
+    pub const fn is_rate(self) -> bool {
+        matches!(self, Self::JankRate | Self::BigJankRate)
+    }
+
+    /// The value of the metric in `sample`
+    #[must_use]
+    pub fn value(self, sample: &Sample) -> f64 {
+        let summary = &sample.summary;
+        match self {
+            Self::AverageFps => summary.average_fps,
+            Self::Low1PercentFps => summary.low_1_percent_fps,
+            Self::Low01PercentFps => summary.low_0_1_percent_fps,
+            Self::Median => millis(summary.median),
+            Self::P90 => millis(summary.p90),
+            Self::P95 => millis(summary.p95),
+            Self::P99 => millis(summary.p99),
+            Self::JankRate => sample.jank_rate(),
+            Self::BigJankRate => sample.big_jank_rate(),
+        }
+    }
+}
+
+/// The change of a [`Metric`] between two sessions
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Delta {
+    pub metric: Metric,
+    /// The value in the base session
+    pub base: f64,
+    /// The value in the new session
+    pub new: f64,
+}
+
+impl Delta {
+    /// `new - base`
+    #[must_use]
+    pub fn change(&self) -> f64 {
+        self.new - self.base
+    }
+
+    /// The change relative to the base, in percent, `None` if the base is 0
+    #[must_use]
+    pub fn percent(&self) -> Option<f64> {
+        (self.base != 0.0).then(|| self.change() / self.base * 100.0)
+    }
+
+    /// How much worse the new session is, negative if it is better
+    ///
+    /// In percentage points for [rates](Metric::is_rate), in percent of the base otherwise
+    #[must_use]
+    pub fn regression(&self) -> f64 {
+        let change = if self.metric.is_rate() {
+            self.change()
+        } else {
+            self.percent().unwrap_or_default()
+        };
+
+        if self.metric.higher_is_better() {
+            -change
+        } else {
+            change
+        }
+    }
+}
+
+/// A two-sided Mann-Whitney U test of whether the frametimes of two sessions differ
+///
+/// Uses the normal approximation with tie correction, meaningful from a few dozen frames per session
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use frame_analyzer::compare::MannWhitney;
+///
+/// let base: Vec<_> = (10..40).map(Duration::from_millis).collect();
+/// let new: Vec<_> = (12..42).map(Duration::from_millis).collect();
+///
+/// // a small shift of overlapping frametimes is not significant
+/// let test = MannWhitney::test(&base, &new).unwrap();
+/// assert!(test.p_value > 0.05);
+/// assert!(test.effect > 0.5);
+///
+/// let slower: Vec<_> = (30..60).map(Duration::from_millis).collect();
+/// assert!(MannWhitney::test(&base, &slower).unwrap().p_value < 0.001);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MannWhitney {
+    /// The U statistic of the new session: pairs of frames where the new one is longer, ties count half
+    pub u: f64,
+    /// The standard score of `u`, positive if the new frames tend to be longer
+    pub z: f64,
+    /// The probability of a difference at least this large between sessions with the same distribution
+    pub p_value: f64,
+    /// `u` over the number of pairs: the probability that a new frame is longer than a base frame
+    pub effect: f64,
+}
+
+impl MannWhitney {
+    /// Test the frametimes of `base` against `new`, `None` if either is empty
+    #[must_use]
+    pub fn test(base: &[Duration], new: &[Duration]) -> Option<Self> {
+        if base.is_empty() || new.is_empty() {
+            return None;
+        }
+
+        let mut frametimes: Vec<_> = base
+            .iter()
+            .map(|&frametime| (frametime, false))
+            .chain(new.iter().map(|&frametime| (frametime, true)))
+            .collect();
+        frametimes.sort_unstable_by_key(|&(frametime, _)| frametime);
+
+        // tied frametimes share the average of their ranks
+        let mut rank_sum = 0.0;
+        let mut ties = 0.0;
+        let mut ranked = 0;
+        for group in frametimes.chunk_by(|a, b| a.0 == b.0) {
+            let len = group.len() as f64;
+            let rank = (ranked as f64).mul_add(2.0, len + 1.0) / 2.0;
+            let from_new = group.iter().filter(|(_, is_new)| *is_new).count();
+            rank_sum += rank * from_new as f64;
+            ties += len.powi(3) - len;
+            ranked += group.len();
+        }
+
+        let (n1, n2) = (base.len() as f64, new.len() as f64);
+        let n = n1 + n2;
+        let pairs = n1 * n2;
+        let u = n2.mul_add(-(n2 + 1.0) / 2.0, rank_sum);
+        let variance = pairs / 12.0 * (n + 1.0 - ties / (n * (n - 1.0)));
+
+        let z = if variance > 0.0 {
+            let diff = u - pairs / 2.0;
+            // continuity correction
+            (diff.abs() - 0.5).max(0.0).copysign(diff) / variance.sqrt()
+        } else {
+            0.0
+        };
+
+        Some(Self {
+            u,
+            z,
+            p_value: erfc(z.abs() / SQRT_2).min(1.0),
+            effect: u / pairs,
+        })
+    }
+
+    /// Whether the difference is significant at level `alpha`, e.g. 0.05
+    #[must_use]
+    pub fn is_significant(&self, alpha: f64) -> bool {
+        self.p_value < alpha
+    }
+}
+
+/// The regressions tolerated by [`Comparison::regressions`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Thresholds {
+    /// Largest tolerated [regression](Delta::regression) of the fps & frametimes, in percent
+    pub max_regression_percent: f64,
+    /// Largest tolerated increase of the jank rates, in percentage points
+    pub max_jank_rate_increase: f64,
+    /// Significance level of the [`MannWhitney`] test, above it nothing is a regression
+    pub alpha: f64,
+}
+
+impl Default for Thresholds {
+    /// 5% regression, 1 percentage point more janks, significant at 0.05
+    fn default() -> Self {
+        Self {
+            max_regression_percent: 5.0,
+            max_jank_rate_increase: 1.0,
+            alpha: 0.05,
+        }
+    }
+}
+
+/// The comparison of a base & a new session
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Comparison {
+    pub base: Sample,
+    pub new: Sample,
+    /// Whether the frametimes differ, `None` if the frametimes were not available
+    pub significance: Option<MannWhitney>,
+}
+
+impl Comparison {
+    /// Compare the statistics of two sessions, e.g. from [`SummaryRecord`](crate::export::SummaryRecord)s
+    ///
+    /// Without the frametimes nothing tells noise from a regression, prefer [`Comparison::from_frametimes`]
+    #[must_use]
+    pub const fn new(base: Sample, new: Sample) -> Self {
+        Self {
+            base,
+            new,
+            significance: None,
+        }
+    }
+
+    /// Compare the frametimes of two sessions, `None` if either is empty
+    #[must_use]
+    pub fn from_frametimes(
+        base: &[Duration],
+        base_janks: JankCounts,
+        new: &[Duration],
+        new_janks: JankCounts,
+    ) -> Option<Self> {
+        Some(Self {
+            base: Sample::new(summary(base)?, base_janks),
+            new: Sample::new(summary(new)?, new_janks),
+            significance: MannWhitney::test(base, new),
+        })
+    }
+
+    /// The change of `metric`
+    #[must_use]
+    pub fn delta(&self, metric: Metric) -> Delta {
+        Delta {
+            metric,
+            base: metric.value(&self.base),
+            new: metric.value(&self.new),
+        }
+    }
+
+    /// The changes of all [`Metric::ALL`]
+    pub fn deltas(&self) -> impl Iterator<Item = Delta> + '_ {
+        Metric::ALL.into_iter().map(|metric| self.delta(metric))
+    }
+
+    /// The deltas worse than `thresholds`, none if the frametimes do not differ significantly
+    #[must_use]
+    pub fn regressions(&self, thresholds: &Thresholds) -> Vec<Delta> {
+        if self
+            .significance
+            .is_some_and(|test| !test.is_significant(thresholds.alpha))
+        {
+            return Vec::new();
+        }
+
+        self.deltas()
+            .filter(|delta| {
+                let max = if delta.metric.is_rate() {
+                    thresholds.max_jank_rate_increase
+                } else {
+                    thresholds.max_regression_percent
+                };
+                delta.regression() > max
+            })
+            .collect()
+    }
+}
+
+fn summary(frametimes: &[Duration]) -> Option<StatsSummary> {
+    let mut stats = FrameStats::new(Window::Frames(frametimes.len()));
+    for &frametime in frametimes {
+        stats.push_frametime(frametime);
+    }
+    stats.summary()
+}
+
+fn rate(count: usize, frames: usize) -> f64 {
+    if frames == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / frames as f64
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// The complementary error function, with a fractional error below 1.2e-7
+fn erfc(x: f64) -> f64 {
+    let t = 1.0 / 0.5f64.mul_add(x.abs(), 1.0);
+    let poly = [
+        -0.822_152_23,
+        1.488_515_87,
+        -1.135_203_98,
+        0.278_868_07,
+        -0.186_288_06,
+        0.096_784_18,
+        0.374_091_96,
+        1.000_023_68,
+        -1.265_512_23,
+    ]
+    .into_iter()
+    .fold(0.170_872_77, |acc: f64, coefficient| {
+        acc.mul_add(t, coefficient)
+    });
+    let result = t * (-x).mul_add(x, poly).exp();
+
+    if x >= 0.0 { result } else { 2.0 - result }
+}
@@ -0,0 +1,454 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Share one [`Analyzer`] between several processes over a unix socket
+//!
+//! Only one process can own the ebpf programs, the [`Daemon`] owns the analyzer and serves the frames
+//! to every connected [`Client`], see [`protocol`]. The client side lives in the `frame-analyzer-client` crate,
+//! which unprivileged tools can depend on without linking the ebpf loader. Attachments are reference counted across the clients:
+//! an app stays attached until every client attaching it detached or disconnected
+//!
+//! # Examples
+//!
+//! ```
+//! use std::{
+//!     sync::{Arc, atomic::{AtomicBool, Ordering}},
+//!     thread,
+//!     time::Duration,
+//! };
+//!
+//! use frame_analyzer::{
+//!     Analyzer, FrameSignal,
+//!     daemon::{Client, Daemon},
+//!     source::MemorySource,
+//! };
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let path = std::env::temp_dir().join("frame-analyzer-daemon-doc.sock");
+//! let (source, sender) = MemorySource::new()?;
+//! let mut daemon = Daemon::bind(Analyzer::with_source(source)?, &path)?;
+//!
+//! let running = Arc::new(AtomicBool::new(true));
+//! let server = {
+//!     let running = running.clone();
+//!     thread::spawn(move || daemon.run(&running))
+//! };
+//!
+//! let mut client = Client::connect(&path)?;
+//! client.attach_app(42)?;
+//!
+//! sender.send(FrameSignal::new(0, 0x7f00, 0, 42));
+//! sender.send(FrameSignal::new(16_000_000, 0x7f00, 0, 42));
+//! assert_eq!(
+//!     client.recv_timeout(Duration::from_secs(5)),
+//!     Some((42, Duration::from_millis(16)))
+//! );
+//!
+//! running.store(false, Ordering::Release);
+//! server.join().unwrap()?;
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, BufReader},
+    net::Shutdown,
+    os::unix::{
+        fs::{self as unix_fs, FileTypeExt, PermissionsExt},
+        io::AsRawFd,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::Duration,
+};
+
+pub use frame_analyzer_client::{Client, ClientError, protocol};
+use protocol::{Frame, Message, PROTOCOL_VERSION, Request};
+
+use crate::{Analyzer, FrameEvent, Pid, RecvOutcome, error::Result};
+
+/// How long a request may wait while the analyzer waits for frames, and how often the stop flag is checked
+const REQUEST_LATENCY: Duration = Duration::from_millis(50);
+/// A client with this many bytes it hasn't read yet is disconnected, so a slow client can't hold the others back
+const MAX_QUEUED: usize = 64 * 1024;
+
+type ClientId = u64;
+
+enum ClientEvent {
+    Connected(ClientId, UnixStream),
+    Request(ClientId, Request),
+    Disconnected(ClientId),
+}
+
+#[derive(Default)]
+struct Session {
+    stream: Option<UnixStream>,
+    /// Encoded messages the socket didn't take yet
+    queued: Vec<u8>,
+    attached: HashSet<Pid>,
+    subscribed: HashSet<Pid>,
+    all: bool,
+}
+
+impl Session {
+    /// Queue `message` and send as much as the socket takes without blocking, `false` if the client is lost
+    fn send(&mut self, message: &Message) -> bool {
+        protocol::write_message(&mut self.queued, message).is_ok()
+            && self.queued.len() <= MAX_QUEUED
+            && self.flush()
+    }
+
+    /// Send the queued messages without blocking, `false` if the client is lost
+    fn flush(&mut self) -> bool {
+        let Some(ref stream) = self.stream else {
+            return false;
+        };
+
+        while !self.queued.is_empty() {
+            // the reader thread shares the socket, so don't switch it to non-blocking and only send without waiting
+            let sent = unsafe {
+                libc::send(
+                    stream.as_raw_fd(),
+                    self.queued.as_ptr().cast(),
+                    self.queued.len(),
+                    libc::MSG_DONTWAIT | libc::MSG_NOSIGNAL,
+                )
+            };
+            if sent < 0 {
+                match io::Error::last_os_error().kind() {
+                    io::ErrorKind::WouldBlock => return true,
+                    io::ErrorKind::Interrupted => continue,
+                    _ => return false,
+                }
+            }
+            self.queued.drain(..sent as usize);
+        }
+
+        true
+    }
+}
+
+/// Serves the frames of an [`Analyzer`] on a unix socket
+pub struct Daemon {
+    analyzer: Analyzer,
+    path: PathBuf,
+    events: Receiver<ClientEvent>,
+    clients: HashMap<ClientId, Session>,
+    attached: HashMap<Pid, usize>,
+}
+
+impl Daemon {
+    /// Listen on the unix socket at `path`, replacing the socket file of a daemon which is not running anymore
+    ///
+    /// The socket is only accessible to the owner & the group of the daemon, any client can attach any pid,
+    /// see [`Daemon::set_group`] to let the unprivileged clients of a group connect
+    ///
+    /// # Errors
+    ///
+    /// - `IOError` if the socket cannot be created
+    /// - `IOError` of kind `AlreadyExists` if `path` is not a socket, `AddrInUse` if another daemon listens on it
+    pub fn bind<P: AsRef<Path>>(analyzer: Analyzer, path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        remove_stale_socket(&path)?;
+
+        let listener = UnixListener::bind(&path)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o660))?;
+
+        let (sender, events) = mpsc::channel();
+        thread::Builder::new()
+            .name("daemon-accept".into())
+            .spawn(move || accept(&listener, &sender))?;
+
+        Ok(Self {
+            analyzer,
+            path,
+            events,
+            clients: HashMap::new(),
+            attached: HashMap::new(),
+        })
+    }
+
+    /// Let the users of group `gid` connect, e.g. 2000 for the `shell` user of Android
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if the group of the socket cannot be changed
+    pub fn set_group(&self, gid: u32) -> Result<()> {
+        unix_fs::chown(&self.path, None, Some(gid))?;
+        Ok(())
+    }
+
+    /// Serve the clients until `running` is `false`
+    ///
+    /// The apps which exit are detached, their subscribers get a [`Message::Exited`]
+    ///
+    /// # Errors
+    ///
+    /// Any error of [`Analyzer::recv_result`] & [`Analyzer::detach_exited`], the errors of a request are sent to its client instead
+    pub fn run(&mut self, running: &AtomicBool) -> Result<()> {
+        while running.load(Ordering::Acquire) {
+            while let Ok(event) = self.events.try_recv() {
+                self.handle(event);
+            }
+            self.flush();
+
+            if self.attached.is_empty() || self.analyzer.is_exhausted() {
+                // nothing to poll, wait for the clients instead
+                match self.events.recv_timeout(REQUEST_LATENCY) {
+                    Ok(event) => self.handle(event),
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            } else if let RecvOutcome::Frame(event) =
+                self.analyzer.recv_result(Some(REQUEST_LATENCY))?
+            {
+                self.broadcast(&event);
+            }
+
+            for pid in self.analyzer.detach_exited()? {
+                self.exited(pid);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The analyzer of the daemon, e.g. to set the refresh rate provider
+    pub const fn analyzer(&mut self) -> &mut Analyzer {
+        &mut self.analyzer
+    }
+
+    fn handle(&mut self, event: ClientEvent) {
+        match event {
+            ClientEvent::Connected(id, stream) => {
+                self.clients.entry(id).or_default().stream = Some(stream);
+                self.send(
+                    id,
+                    &Message::Hello {
+                        version: PROTOCOL_VERSION,
+                    },
+                );
+            }
+            ClientEvent::Request(id, request) => {
+                let response = match self.request(id, request) {
+                    Ok(()) => Message::Ok,
+                    Err(e) => Message::Error(e.to_string()),
+                };
+                self.send(id, &response);
+            }
+            ClientEvent::Disconnected(id) => self.disconnect(id),
+        }
+    }
+
+    fn request(&mut self, id: ClientId, request: Request) -> Result<()> {
+        let client = self.clients.entry(id).or_default();
+
+        match request {
+            Request::Attach(pid) => {
+                if client.attached.insert(pid) {
+                    let count = self.attached.entry(pid).or_default();
+                    if *count == 0
+                        && let Err(e) = self.analyzer.attach_app(pid)
+                    {
+                        self.attached.remove(&pid);
+                        client.attached.remove(&pid);
+                        return Err(e);
+                    }
+                    *count += 1;
+                }
+                client.subscribed.insert(pid);
+            }
+            Request::Detach(pid) => {
+                client.subscribed.remove(&pid);
+                if client.attached.remove(&pid) {
+                    self.release(pid)?;
+                }
+            }
+            Request::Subscribe(Some(pid)) => {
+                client.subscribed.insert(pid);
+            }
+            Request::Subscribe(None) => client.all = true,
+            Request::Unsubscribe(Some(pid)) => {
+                client.subscribed.remove(&pid);
+            }
+            Request::Unsubscribe(None) => client.all = false,
+        }
+
+        Ok(())
+    }
+
+    /// Drop an attachment of `pid`, detach it once nobody holds it
+    fn release(&mut self, pid: Pid) -> Result<()> {
+        if let Some(count) = self.attached.get_mut(&pid) {
+            *count -= 1;
+            if *count == 0 {
+                self.attached.remove(&pid);
+                self.analyzer.detach_app(pid)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The analyzer detached `pid` as it exited, drop its attachments and tell its subscribers
+    fn exited(&mut self, pid: Pid) {
+        self.attached.remove(&pid);
+
+        let subscribers: Vec<_> = self
+            .clients
+            .iter_mut()
+            .filter_map(|(id, client)| {
+                let attached = client.attached.remove(&pid);
+                let subscribed = client.subscribed.remove(&pid);
+                (attached || subscribed || client.all).then_some(*id)
+            })
+            .collect();
+        for id in subscribers {
+            self.send(id, &Message::Exited(pid));
+        }
+    }
+
+    fn disconnect(&mut self, id: ClientId) {
+        if let Some(client) = self.clients.remove(&id) {
+            // the reader thread holds a clone of the stream, shut it down so the client sees the disconnection
+            if let Some(ref stream) = client.stream {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+            for pid in client.attached {
+                let _ = self.release(pid);
+            }
+        }
+    }
+
+    fn send(&mut self, id: ClientId, message: &Message) {
+        let sent = self
+            .clients
+            .get_mut(&id)
+            .is_some_and(|client| client.send(message));
+
+        if !sent {
+            self.disconnect(id);
+        }
+    }
+
+    /// Send what the clients didn't take yet, disconnecting the lost ones
+    fn flush(&mut self) {
+        let lost: Vec<_> = self
+            .clients
+            .iter_mut()
+            .filter(|(_, client)| !client.queued.is_empty())
+            .filter_map(|(id, client)| (!client.flush()).then_some(*id))
+            .collect();
+        for id in lost {
+            self.disconnect(id);
+        }
+    }
+
+    fn broadcast(&mut self, event: &FrameEvent) {
+        let message = Message::Frame(Frame {
+            pid: event.pid,
+            surface: event.surface as u64,
+            timestamp_ns: event.timestamp_ns,
+            frametime_ns: event.frametime.as_nanos() as u64,
+            refresh_period_ns: event
+                .refresh_period
+                .map_or(0, |period| period.as_nanos() as u64),
+        });
+
+        let subscribers: Vec<_> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.all || client.subscribed.contains(&event.pid))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in subscribers {
+            self.send(id, &message);
+        }
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        // the reader threads hold clones of the streams, shut them down so the clients see the disconnection
+        for stream in self
+            .clients
+            .values()
+            .filter_map(|client| client.stream.as_ref())
+        {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        // close the channel and wake the acceptor, it stops on the closed channel
+        self.events = mpsc::channel().1;
+        let _ = UnixStream::connect(&self.path);
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Remove the socket file at `path` if nothing listens on it, any other file is left untouched
+fn remove_stale_socket(path: &Path) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+        Ok(metadata) if !metadata.file_type().is_socket() => {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "not a socket").into());
+        }
+        Ok(_) => (),
+    }
+
+    if UnixStream::connect(path).is_ok() {
+        return Err(
+            io::Error::new(io::ErrorKind::AddrInUse, "another daemon listens on it").into(),
+        );
+    }
+    fs::remove_file(path)?;
+
+    Ok(())
+}
+
+fn accept(listener: &UnixListener, events: &Sender<ClientEvent>) {
+    for (id, stream) in (0..).zip(listener.incoming()) {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let Ok(writer) = stream.try_clone() else {
+            continue;
+        };
+        if events.send(ClientEvent::Connected(id, writer)).is_err() {
+            return;
+        }
+
+        let events = events.clone();
+        let _ = thread::Builder::new()
+            .name("daemon-client".into())
+            .spawn(move || {
+                let mut reader = BufReader::new(stream);
+                while let Ok(Some(request)) = protocol::read_request(&mut reader) {
+                    if events.send(ClientEvent::Request(id, request)).is_err() {
+                        return;
+                    }
+                }
+                let _ = events.send(ClientEvent::Disconnected(id));
+            });
+    }
+}
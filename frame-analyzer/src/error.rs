@@ -23,7 +23,11 @@ use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, AnalyzerError>;
 
+/// The errors of the analyzer
+///
+/// Non exhaustive, the variants depend on the enabled features, e.g. `SerdeError` needs `serde`
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum AnalyzerError {
     #[error(transparent)]
     EbpfError(#[from] EbpfError),
@@ -37,4 +41,13 @@ pub enum AnalyzerError {
     AppNotFound,
     #[error("Map error")]
     MapError,
+    #[error("Histogram is only collected in FrameMode::Histogram")]
+    HistogramDisabled,
+    #[error("Invalid trace file: {0}")]
+    InvalidTrace(&'static str),
+    #[error("Replay speed must be a positive & finite factor")]
+    InvalidReplaySpeed,
+    #[cfg(feature = "serde")]
+    #[error(transparent)]
+    SerdeError(#[from] serde_json::Error),
 }
@@ -0,0 +1,126 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::time::Duration;
+
+use crate::{Pid, refresh};
+
+/// A frame of an attached application
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use frame_analyzer::FrameEvent;
+///
+/// let event = FrameEvent::new(1, 0x7f00, 1_000_000_000, Duration::from_millis(16));
+/// assert_eq!(event.fps(), 62.5);
+/// assert_eq!(event.vsyncs, None);
+///
+/// // a 60 Hz display, the frame spanned one vsync interval
+/// let event = event.with_refresh_period(Duration::from_micros(16_667));
+/// assert_eq!(event.vsyncs, Some(1));
+/// assert_eq!(event.missed_vsyncs(), Some(0));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct FrameEvent {
+    /// The pid of the application
+    pub pid: Pid,
+    /// Address of the `android::Surface` the frame was queued to, identifying the surface within the process
+    pub surface: usize,
+    /// When the frame was queued, in nanoseconds of `CLOCK_MONOTONIC`
+    pub timestamp_ns: u64,
+    /// Time since the previous frame of the same surface
+    pub frametime: Duration,
+    /// The display refresh period when the frame was queued, `None` if it is unknown, see [`refresh`](crate::refresh)
+    pub refresh_period: Option<Duration>,
+    /// Number of vsync intervals the frame spanned, `None` if the refresh period is unknown
+    pub vsyncs: Option<u32>,
+}
+
+impl FrameEvent {
+    /// Create a frame event
+    #[must_use]
+    pub const fn new(pid: Pid, surface: usize, timestamp_ns: u64, frametime: Duration) -> Self {
+        Self {
+            pid,
+            surface,
+            timestamp_ns,
+            frametime,
+            refresh_period: None,
+            vsyncs: None,
+        }
+    }
+
+    /// Annotate the frame with the display refresh period and the number of vsync intervals it spanned
+    #[must_use]
+    pub fn with_refresh_period(mut self, period: Duration) -> Self {
+        self.refresh_period = Some(period);
+        self.vsyncs = Some(refresh::vsyncs(self.frametime, period));
+        self
+    }
+
+    /// Number of vsyncs the frame missed, i.e. the vsync intervals it spanned beyond the first one
+    #[must_use]
+    pub fn missed_vsyncs(&self) -> Option<u32> {
+        self.vsyncs.map(|vsyncs| vsyncs.saturating_sub(1))
+    }
+
+    /// The instantaneous fps of this frame, `f64::INFINITY` for a zero frametime
+    #[must_use]
+    pub fn fps(&self) -> f64 {
+        1.0 / self.frametime.as_secs_f64()
+    }
+}
+
+/// The result of [`Analyzer::recv_result`](crate::Analyzer::recv_result) & [`Analyzer::try_recv`](crate::Analyzer::try_recv)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvOutcome {
+    /// A frame of an attached application
+    Frame(FrameEvent),
+    /// A signal of an attached application which produced no frame:
+    /// the first one of a surface, or one of a surface which is not selected
+    Skipped {
+        /// The pid of the application
+        pid: Pid,
+        /// The surface the frame was queued to
+        surface: usize,
+    },
+    /// A signal of an application which is not attached, e.g. queued just before it was detached
+    Detached(Pid),
+    /// No frame before the timeout
+    Timeout,
+    /// Woken by an [`AnalyzerHandle`](crate::AnalyzerHandle)
+    Interrupted,
+    /// All frames have been received and no more will come, see [`Analyzer::is_exhausted`](crate::Analyzer::is_exhausted)
+    Exhausted,
+}
+
+impl RecvOutcome {
+    /// The frame, if any
+    #[must_use]
+    pub const fn frame(self) -> Option<FrameEvent> {
+        match self {
+            Self::Frame(event) => Some(event),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,69 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Export frames to the formats of other tools
+//!
+//! Every exporter takes [`FrameEvent`](crate::FrameEvent)s, from a live [`Analyzer`](crate::Analyzer)
+//! or from a recorded session through [`replay::events`](crate::replay::events)
+//!
+//! # Examples
+//!
+//! Convert a recorded session to a perfetto trace
+//!
+//! ```
+//! use frame_analyzer::{
+//!     export::PerfettoWriter,
+//!     replay,
+//!     trace::{SessionMetadata, TraceReader, TraceRecord, TraceWriter},
+//! };
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! # let dir = std::env::temp_dir();
+//! # let session = dir.join("frame-analyzer-export-doc.fatrace");
+//! # let metadata = SessionMetadata { package: "com.example.game".into(), ..SessionMetadata::default() };
+//! # let mut recorder = TraceWriter::create(&session, &metadata)?;
+//! # for i in 0..100 {
+//! #     recorder.write(&TraceRecord::new(i * 16_666_667, 42, 0x7f00, 0))?;
+//! # }
+//! # recorder.finish()?;
+//! let events = replay::events(TraceReader::open(&session)?);
+//! let package = events.metadata().package.clone();
+//!
+//! let mut writer = PerfettoWriter::create(dir.join("session.pftrace"))?;
+//! for event in events {
+//!     let event = event?;
+//!     writer.set_process_name(event.pid, &package);
+//!     writer.write(&event)?;
+//! }
+//! writer.finish()?;
+//! # std::fs::remove_file(&session)?;
+//! # std::fs::remove_file(dir.join("session.pftrace"))?;
+//! # Ok(())
+//! # }
+//! ```
+mod chrome;
+mod perfetto;
+#[cfg(feature = "serde")]
+mod table;
+
+pub use chrome::ChromeTraceWriter;
+pub use perfetto::{ClockSnapshot, PerfettoWriter};
+#[cfg(feature = "serde")]
+pub use table::{
+    CsvWriter, FrameRecord, JsonLinesWriter, Record, SCHEMA_VERSION, SummaryRecord, Versioned,
+};
@@ -0,0 +1,215 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    fmt::{self, Write as _},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::{FrameEvent, Pid, error::Result};
+
+/// Write frames as Chrome Trace Event JSON, to be opened in `chrome://tracing`, [ui.perfetto.dev](https://ui.perfetto.dev) or speedscope
+///
+/// Every frame is a complete (`X`) event from the previous `queueBuffer` to this one, on a thread per surface of the app,
+/// followed by an `FPS` counter (`C`) event unless the frametime is zero.
+/// The events are streamed in the JSON array format, which tolerates a missing closing bracket,
+/// so the file stays readable if the session is interrupted before [`ChromeTraceWriter::finish`]
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use frame_analyzer::{FrameEvent, export::ChromeTraceWriter};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let mut writer = ChromeTraceWriter::new(Vec::new())?;
+/// writer.set_process_name(42, "com.example.game");
+/// writer.write(&FrameEvent::new(42, 0x7f00, 16_000_000, Duration::from_millis(16)))?;
+/// writer.write(&FrameEvent::new(42, 0x7f00, 16_000_000, Duration::ZERO))?;
+///
+/// let json = String::from_utf8(writer.finish()?)?;
+/// assert!(json.starts_with('['));
+/// assert!(json.contains(r#""ph":"X","name":"Frame","pid":42,"tid":1,"ts":0.000,"dur":16000.000"#));
+/// assert_eq!(json.matches(r#""name":"FPS""#).count(), 1);
+/// assert!(json.trim_end().ends_with(']'));
+/// # Ok(())
+/// # }
+/// ```
+pub struct ChromeTraceWriter<W: Write> {
+    inner: W,
+    names: HashMap<Pid, String>,
+    threads: HashMap<(Pid, usize), u32>,
+    processes: HashMap<Pid, u32>,
+    buf: String,
+    first: bool,
+}
+
+impl ChromeTraceWriter<BufWriter<File>> {
+    /// Create a trace file at `path`, truncating it if it exists
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if the file cannot be created or written
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::create(path)?;
+        Self::new(BufWriter::new(file))
+    }
+}
+
+impl<W: Write> ChromeTraceWriter<W> {
+    /// Write a trace to any writer
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if writing fails
+    pub fn new(mut inner: W) -> Result<Self> {
+        inner.write_all(b"[")?;
+
+        Ok(Self {
+            inner,
+            names: HashMap::new(),
+            threads: HashMap::new(),
+            processes: HashMap::new(),
+            buf: String::new(),
+            first: true,
+        })
+    }
+
+    /// Name the process of `pid`, e.g. with its package name, ignored after its first frame
+    pub fn set_process_name<S: Into<String>>(&mut self, pid: Pid, name: S) {
+        if !self.processes.contains_key(&pid) {
+            self.names.insert(pid, name.into());
+        }
+    }
+
+    /// Write a frame
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if writing fails
+    pub fn write(&mut self, event: &FrameEvent) -> Result<()> {
+        self.buf.clear();
+        let pid = event.pid;
+        let tid = self.thread(pid, event.surface);
+
+        let end = event.timestamp_ns;
+        let begin = end.saturating_sub(event.frametime.as_nanos() as u64);
+        let vsyncs = event
+            .vsyncs
+            .map(|vsyncs| format!(r#","vsyncs":{vsyncs}"#))
+            .unwrap_or_default();
+        self.push(format_args!(
+            r#"{{"ph":"X","name":"Frame","pid":{pid},"tid":{tid},"ts":{},"dur":{},"args":{{"frametime_ms":{:.3}{vsyncs}}}}}"#,
+            micros(begin),
+            micros(end - begin),
+            event.frametime.as_secs_f64() * 1000.0,
+        ));
+        // a zero frametime has no fps, and JSON has no infinity
+        let fps = event.fps();
+        if fps.is_finite() {
+            self.push(format_args!(
+                r#"{{"ph":"C","name":"FPS","pid":{pid},"ts":{},"args":{{"fps":{fps:.3}}}}}"#,
+                micros(end),
+            ));
+        }
+
+        self.inner.write_all(self.buf.as_bytes())?;
+        Ok(())
+    }
+
+    /// Flush the trace
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if writing fails
+    pub fn flush(&mut self) -> Result<()> {
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// Close the JSON array and return the inner writer
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if writing fails
+    pub fn finish(mut self) -> Result<W> {
+        self.inner.write_all(b"\n]\n")?;
+        self.flush()?;
+        Ok(self.inner)
+    }
+
+    /// The tid of a surface, the metadata events naming the process & thread are written the first time
+    fn thread(&mut self, pid: Pid, surface: usize) -> u32 {
+        if let Some(tid) = self.threads.get(&(pid, surface)) {
+            return *tid;
+        }
+
+        if let Entry::Vacant(entry) = self.processes.entry(pid) {
+            entry.insert(0);
+            if let Some(name) = self.names.get(&pid) {
+                let name = escape(name);
+                self.push(format_args!(
+                    r#"{{"ph":"M","name":"process_name","pid":{pid},"args":{{"name":"{name}"}}}}"#,
+                ));
+            }
+        }
+
+        let surfaces = self.processes.entry(pid).or_default();
+        *surfaces += 1;
+        let tid = *surfaces;
+        self.threads.insert((pid, surface), tid);
+        self.push(format_args!(
+            r#"{{"ph":"M","name":"thread_name","pid":{pid},"tid":{tid},"args":{{"name":"Surface {surface:#x}"}}}}"#,
+        ));
+
+        tid
+    }
+
+    /// Append an event to the buffer, separated from the previous one
+    fn push(&mut self, event: fmt::Arguments) {
+        let separator = if self.first { "\n" } else { ",\n" };
+        self.first = false;
+        self.buf.push_str(separator);
+        let _ = self.buf.write_fmt(event);
+    }
+}
+
+/// Nanoseconds to the microseconds of the trace event format
+fn micros(ns: u64) -> String {
+    format!("{}.{:03}", ns / 1000, ns % 1000)
+}
+
+/// Escape a JSON string
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,375 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use crate::{
+    FrameEvent, Pid,
+    error::Result,
+    jank::{JankDetector, JankKind, JankRule},
+    stats::Window,
+    trace::put_varint,
+};
+
+/// `BUILTIN_CLOCK_MONOTONIC`, the clock of the frame timestamps
+const CLOCK_MONOTONIC: u64 = 3;
+/// `BUILTIN_CLOCK_BOOTTIME`, the default clock of perfetto traces
+const CLOCK_BOOTTIME: u64 = 6;
+/// `SEQ_INCREMENTAL_STATE_CLEARED`
+const SEQ_INCREMENTAL_STATE_CLEARED: u64 = 1;
+const SEQUENCE_ID: u64 = 1;
+
+const TYPE_SLICE_BEGIN: u64 = 1;
+const TYPE_SLICE_END: u64 = 2;
+const TYPE_INSTANT: u64 = 3;
+const TYPE_COUNTER: u64 = 4;
+
+/// Offset between `CLOCK_BOOTTIME` and `CLOCK_MONOTONIC`, lets perfetto align the frames with system traces
+///
+/// The clocks only drift apart while the device is suspended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClockSnapshot {
+    /// `CLOCK_BOOTTIME` in nanoseconds
+    pub boottime_ns: u64,
+    /// `CLOCK_MONOTONIC` in nanoseconds, at the same instant
+    pub monotonic_ns: u64,
+}
+
+impl ClockSnapshot {
+    /// Read both clocks now, only meaningful on the recording device and before it reboots
+    #[must_use]
+    pub fn now() -> Self {
+        Self {
+            boottime_ns: clock_ns(libc::CLOCK_BOOTTIME),
+            monotonic_ns: clock_ns(libc::CLOCK_MONOTONIC),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Track {
+    Process,
+    Surface(usize),
+    Fps,
+    Jank,
+}
+
+/// Write frames as a perfetto protobuf trace, to be opened in [ui.perfetto.dev](https://ui.perfetto.dev)
+///
+/// Every app gets a process track with
+///
+/// - a slice per frame, from the previous `queueBuffer` to this one, on a track per surface
+/// - an `FPS` counter
+/// - a `Jank` track with an instant per janky frame, classified by [`JankRule::vsync`] if the refresh period is known
+///   or [`JankRule::PerfDog`] otherwise
+///
+/// The timestamps stay in `CLOCK_MONOTONIC`, a [`ClockSnapshot`] tells perfetto how to align them with traces captured on the same device
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use frame_analyzer::{FrameEvent, export::PerfettoWriter};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let mut writer = PerfettoWriter::new(Vec::new())?;
+/// writer.set_process_name(42, "com.example.game");
+/// for i in 1..=60 {
+///     let frametime = if i % 30 == 0 { Duration::from_millis(150) } else { Duration::from_millis(16) };
+///     writer.write(&FrameEvent::new(42, 0x7f00, i * 16_000_000, frametime))?;
+/// }
+///
+/// let trace = writer.finish()?;
+/// assert!(!trace.is_empty());
+/// # Ok(())
+/// # }
+/// ```
+pub struct PerfettoWriter<W: Write> {
+    inner: W,
+    uuids: HashMap<(Pid, Track), u64>,
+    names: HashMap<Pid, String>,
+    jank: HashMap<Pid, JankDetector>,
+    last_end: HashMap<(Pid, usize), u64>,
+}
+
+impl PerfettoWriter<BufWriter<File>> {
+    /// Create a perfetto trace file at `path`, truncating it if it exists
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if the file cannot be created or written
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::create(path)?;
+        Self::new(BufWriter::new(file))
+    }
+}
+
+impl<W: Write> PerfettoWriter<W> {
+    /// Write a perfetto trace to any writer, with an identity [`ClockSnapshot`]
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if writing fails
+    pub fn new(inner: W) -> Result<Self> {
+        Self::with_clock_snapshot(inner, ClockSnapshot::default())
+    }
+
+    /// Write a perfetto trace to any writer, aligned with the other traces of the device by `snapshot`
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if writing fails
+    pub fn with_clock_snapshot(mut inner: W, snapshot: ClockSnapshot) -> Result<Self> {
+        let mut clocks = Proto::default();
+        for (clock, timestamp) in [
+            (CLOCK_BOOTTIME, snapshot.boottime_ns),
+            (CLOCK_MONOTONIC, snapshot.monotonic_ns),
+        ] {
+            let mut entry = Proto::default();
+            entry.varint(1, clock);
+            entry.varint(2, timestamp);
+            clocks.message(1, &entry);
+        }
+
+        let mut packet = Proto::default();
+        packet.message(6, &clocks);
+        packet.varint(10, SEQUENCE_ID);
+        packet.varint(13, SEQ_INCREMENTAL_STATE_CLEARED);
+        write_packet(&mut inner, &packet)?;
+
+        Ok(Self {
+            inner,
+            uuids: HashMap::new(),
+            names: HashMap::new(),
+            jank: HashMap::new(),
+            last_end: HashMap::new(),
+        })
+    }
+
+    /// Name the process track of `pid`, e.g. with its package name, ignored after its first frame
+    pub fn set_process_name<S: Into<String>>(&mut self, pid: Pid, name: S) {
+        if !self.uuids.contains_key(&(pid, Track::Process)) {
+            self.names.insert(pid, name.into());
+        }
+    }
+
+    /// Write a frame
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if writing fails
+    pub fn write(&mut self, event: &FrameEvent) -> Result<()> {
+        let end = event.timestamp_ns;
+        let begin = end.saturating_sub(event.frametime.as_nanos() as u64);
+        // keep the slices of a surface from overlapping when the frametime was computed by the ebpf program
+        let begin = self
+            .last_end
+            .insert((event.pid, event.surface), end)
+            .map_or(begin, |last_end| begin.max(last_end));
+
+        let surface = self.track(event.pid, Track::Surface(event.surface))?;
+        self.track_event(begin, surface, TYPE_SLICE_BEGIN, |track_event| {
+            track_event.string(23, "Frame");
+            track_event.message(4, &annotation("frametime_ms", millis(event.frametime)));
+            if let Some(vsyncs) = event.vsyncs {
+                track_event.message(4, &annotation("vsyncs", f64::from(vsyncs)));
+            }
+        })?;
+        self.track_event(end, surface, TYPE_SLICE_END, |_| ())?;
+
+        let fps = self.track(event.pid, Track::Fps)?;
+        self.track_event(end, fps, TYPE_COUNTER, |track_event| {
+            track_event.double(44, event.fps());
+        })?;
+
+        let rule = event
+            .refresh_period
+            .map_or(JankRule::PerfDog, JankRule::vsync);
+        let detector = self
+            .jank
+            .entry(event.pid)
+            .or_insert_with(|| JankDetector::new(rule, Window::Frames(1)));
+        detector.set_rule(rule);
+        let name = match detector.push(event) {
+            JankKind::Smooth => return Ok(()),
+            JankKind::Jank => "Jank",
+            JankKind::BigJank => "Big jank",
+        };
+
+        let jank = self.track(event.pid, Track::Jank)?;
+        self.track_event(end, jank, TYPE_INSTANT, |track_event| {
+            track_event.string(23, name);
+            track_event.message(4, &annotation("frametime_ms", millis(event.frametime)));
+        })
+    }
+
+    /// Flush the trace
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if writing fails
+    pub fn flush(&mut self) -> Result<()> {
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// Flush the trace and return the inner writer
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if writing fails
+    pub fn finish(mut self) -> Result<W> {
+        self.flush()?;
+        Ok(self.inner)
+    }
+
+    /// The uuid of a track, its descriptor is written the first time
+    fn track(&mut self, pid: Pid, track: Track) -> Result<u64> {
+        if let Some(uuid) = self.uuids.get(&(pid, track)) {
+            return Ok(*uuid);
+        }
+
+        let parent = match track {
+            Track::Process => None,
+            _ => Some(self.track(pid, Track::Process)?),
+        };
+        let uuid = self.uuids.len() as u64 + 1;
+        self.uuids.insert((pid, track), uuid);
+
+        let mut descriptor = Proto::default();
+        descriptor.varint(1, uuid);
+        if let Some(parent) = parent {
+            descriptor.varint(5, parent);
+        }
+        match track {
+            Track::Process => {
+                let mut process = Proto::default();
+                process.varint(1, pid as u64);
+                if let Some(name) = self.names.get(&pid) {
+                    process.string(6, name);
+                }
+                descriptor.message(3, &process);
+            }
+            Track::Surface(surface) => descriptor.string(2, &format!("Surface {surface:#x}")),
+            Track::Fps => {
+                descriptor.string(2, "FPS");
+                descriptor.message(8, &Proto::default());
+            }
+            Track::Jank => descriptor.string(2, "Jank"),
+        }
+
+        let mut packet = Proto::default();
+        packet.message(60, &descriptor);
+        packet.varint(10, SEQUENCE_ID);
+        write_packet(&mut self.inner, &packet)?;
+
+        Ok(uuid)
+    }
+
+    fn track_event<F: FnOnce(&mut Proto)>(
+        &mut self,
+        timestamp: u64,
+        track: u64,
+        kind: u64,
+        fill: F,
+    ) -> Result<()> {
+        let mut track_event = Proto::default();
+        track_event.varint(9, kind);
+        track_event.varint(11, track);
+        fill(&mut track_event);
+
+        let mut packet = Proto::default();
+        packet.varint(8, timestamp);
+        packet.varint(58, CLOCK_MONOTONIC);
+        packet.message(11, &track_event);
+        packet.varint(10, SEQUENCE_ID);
+        write_packet(&mut self.inner, &packet)
+    }
+}
+
+/// A protobuf message being encoded
+#[derive(Default)]
+struct Proto(Vec<u8>);
+
+impl Proto {
+    fn key(&mut self, field: u32, wire_type: u32) {
+        put_varint(&mut self.0, u64::from(field << 3 | wire_type));
+    }
+
+    fn varint(&mut self, field: u32, value: u64) {
+        self.key(field, 0);
+        put_varint(&mut self.0, value);
+    }
+
+    fn double(&mut self, field: u32, value: f64) {
+        self.key(field, 1);
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bytes(&mut self, field: u32, bytes: &[u8]) {
+        self.key(field, 2);
+        put_varint(&mut self.0, bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn string(&mut self, field: u32, value: &str) {
+        self.bytes(field, value.as_bytes());
+    }
+
+    fn message(&mut self, field: u32, message: &Self) {
+        self.bytes(field, &message.0);
+    }
+}
+
+/// A `DebugAnnotation` with a double value
+fn annotation(name: &str, value: f64) -> Proto {
+    let mut annotation = Proto::default();
+    annotation.string(10, name);
+    annotation.double(5, value);
+    annotation
+}
+
+/// Write a `TracePacket` as a `packet` field of the `Trace` message
+fn write_packet<W: Write>(writer: &mut W, packet: &Proto) -> Result<()> {
+    let mut field = Proto::default();
+    field.message(1, packet);
+    writer.write_all(&field.0)?;
+    Ok(())
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn clock_ns(clock: libc::clockid_t) -> u64 {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(clock, &raw mut time);
+    }
+    time.tv_sec as u64 * 1_000_000_000 + time.tv_nsec as u64
+}
@@ -0,0 +1,478 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{
+    fmt::{Display, Write as _},
+    fs::File,
+    io::{BufWriter, Write},
+    marker::PhantomData,
+    path::Path,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    FrameEvent, Pid,
+    error::Result,
+    jank::{JankCounts, JankKind},
+    stats::StatsSummary,
+};
+
+/// Version of the columns of [`FrameRecord`] & [`SummaryRecord`]
+///
+/// Columns may be appended without changing it, it is bumped when a column is removed, renamed or changes meaning.
+/// Every export carries it: the first column of a CSV file and a `schema_version` field of every JSON Lines object
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A record with the [`SCHEMA_VERSION`] it was written with, the object of a JSON Lines line
+///
+/// # Examples
+///
+/// ```
+/// use frame_analyzer::export::{SCHEMA_VERSION, SummaryRecord, Versioned};
+///
+/// # fn read(line: &str) -> anyhow::Result<SummaryRecord> {
+/// let versioned: Versioned<SummaryRecord> = serde_json::from_str(line)?;
+/// anyhow::ensure!(versioned.schema_version == SCHEMA_VERSION, "unsupported schema");
+/// # Ok(versioned.record)
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Versioned<R> {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub record: R,
+}
+
+/// A row of a table export
+pub trait Record: Serialize {
+    /// Names of the columns, in order, the same as the serialized field names
+    const COLUMNS: &'static [&'static str];
+
+    /// Append the CSV fields of the row in the order of the [columns](Record::COLUMNS), without a line break
+    fn write_csv(&self, line: &mut String);
+}
+
+/// A frame, the row of a frame table
+///
+/// There is no thread id column, the ebpf program only reports the pid (tgid) of the queueing app,
+/// the `surface` tells the render pipelines of an app apart instead
+///
+/// | column | type | |
+/// |---|---|---|
+/// | `timestamp_ns` | integer | when the frame was queued, nanoseconds of `CLOCK_MONOTONIC` |
+/// | `pid` | integer | the pid of the application |
+/// | `surface` | integer | the surface the frame was queued to |
+/// | `frametime_ns` | integer | time since the previous frame of the surface |
+/// | `refresh_period_ns` | integer, empty if unknown | the display refresh period |
+/// | `vsyncs` | integer, empty if unknown | vsync intervals spanned by the frame |
+/// | `jank` | bool | the frame is a [`JankKind::Jank`] |
+/// | `big_jank` | bool | the frame is a [`JankKind::BigJank`] |
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameRecord {
+    pub timestamp_ns: u64,
+    pub pid: Pid,
+    pub surface: usize,
+    pub frametime_ns: u64,
+    pub refresh_period_ns: Option<u64>,
+    pub vsyncs: Option<u32>,
+    pub jank: bool,
+    pub big_jank: bool,
+}
+
+impl FrameRecord {
+    /// The row of a frame classified as `jank`, e.g. by a [`JankDetector`](crate::jank::JankDetector)
+    #[must_use]
+    pub fn new(event: &FrameEvent, jank: JankKind) -> Self {
+        Self {
+            timestamp_ns: event.timestamp_ns,
+            pid: event.pid,
+            surface: event.surface,
+            frametime_ns: nanos(event.frametime),
+            refresh_period_ns: event.refresh_period.map(nanos),
+            vsyncs: event.vsyncs,
+            jank: jank == JankKind::Jank,
+            big_jank: jank == JankKind::BigJank,
+        }
+    }
+}
+
+impl Record for FrameRecord {
+    const COLUMNS: &'static [&'static str] = &[
+        "timestamp_ns",
+        "pid",
+        "surface",
+        "frametime_ns",
+        "refresh_period_ns",
+        "vsyncs",
+        "jank",
+        "big_jank",
+    ];
+
+    fn write_csv(&self, line: &mut String) {
+        let _ = write!(
+            line,
+            "{},{},{},{},",
+            self.timestamp_ns, self.pid, self.surface, self.frametime_ns
+        );
+        push_optional(line, self.refresh_period_ns);
+        line.push(',');
+        push_optional(line, self.vsyncs);
+        let _ = write!(line, ",{},{}", self.jank, self.big_jank);
+    }
+}
+
+/// A periodic summary of an application, the row of a summary table
+///
+/// | column | type | |
+/// |---|---|---|
+/// | `timestamp_ns` | integer | when the summary was taken, nanoseconds of `CLOCK_MONOTONIC` |
+/// | `pid` | integer | the pid of the application |
+/// | `frames` | integer | number of frames in the window |
+/// | `duration_ns` | integer | sum of the frametimes in the window |
+/// | `average_fps` | float | |
+/// | `min_ns`, `max_ns`, `median_ns`, `p90_ns`, `p95_ns`, `p99_ns` | integer | frametime percentiles |
+/// | `low_1_percent_fps`, `low_0_1_percent_fps` | float | see [`FrameStats::low_fps`](crate::stats::FrameStats::low_fps) |
+/// | `std_dev_ns` | integer | standard deviation of the frametimes |
+/// | `jank`, `big_jank` | integer | janks in the window |
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SummaryRecord {
+    pub timestamp_ns: u64,
+    pub pid: Pid,
+    pub frames: usize,
+    pub duration_ns: u64,
+    pub average_fps: f64,
+    pub min_ns: u64,
+    pub max_ns: u64,
+    pub median_ns: u64,
+    pub p90_ns: u64,
+    pub p95_ns: u64,
+    pub p99_ns: u64,
+    pub low_1_percent_fps: f64,
+    pub low_0_1_percent_fps: f64,
+    pub std_dev_ns: u64,
+    pub jank: usize,
+    pub big_jank: usize,
+}
+
+impl SummaryRecord {
+    /// The row of the statistics & jank counts of `pid` at `timestamp_ns`
+    #[must_use]
+    pub const fn new(
+        timestamp_ns: u64,
+        pid: Pid,
+        summary: &StatsSummary,
+        jank: JankCounts,
+    ) -> Self {
+        Self {
+            timestamp_ns,
+            pid,
+            frames: summary.frames,
+            duration_ns: nanos(summary.duration),
+            average_fps: summary.average_fps,
+            min_ns: nanos(summary.min),
+            max_ns: nanos(summary.max),
+            median_ns: nanos(summary.median),
+            p90_ns: nanos(summary.p90),
+            p95_ns: nanos(summary.p95),
+            p99_ns: nanos(summary.p99),
+            low_1_percent_fps: summary.low_1_percent_fps,
+            low_0_1_percent_fps: summary.low_0_1_percent_fps,
+            std_dev_ns: nanos(summary.std_dev),
+            jank: jank.jank,
+            big_jank: jank.big_jank,
+        }
+    }
+
+    /// The statistics of the row, the inverse of [`SummaryRecord::new`]
+    #[must_use]
+    pub const fn summary(&self) -> StatsSummary {
+        StatsSummary {
+            frames: self.frames,
+            duration: Duration::from_nanos(self.duration_ns),
+            average_fps: self.average_fps,
+            min: Duration::from_nanos(self.min_ns),
+            max: Duration::from_nanos(self.max_ns),
+            median: Duration::from_nanos(self.median_ns),
+            p90: Duration::from_nanos(self.p90_ns),
+            p95: Duration::from_nanos(self.p95_ns),
+            p99: Duration::from_nanos(self.p99_ns),
+            low_1_percent_fps: self.low_1_percent_fps,
+            low_0_1_percent_fps: self.low_0_1_percent_fps,
+            std_dev: Duration::from_nanos(self.std_dev_ns),
+        }
+    }
+
+    /// The jank counts of the row
+    #[must_use]
+    pub const fn jank_counts(&self) -> JankCounts {
+        JankCounts {
+            frames: self.frames,
+            jank: self.jank,
+            big_jank: self.big_jank,
+        }
+    }
+}
+
+impl Record for SummaryRecord {
+    const COLUMNS: &'static [&'static str] = &[
+        "timestamp_ns",
+        "pid",
+        "frames",
+        "duration_ns",
+        "average_fps",
+        "min_ns",
+        "max_ns",
+        "median_ns",
+        "p90_ns",
+        "p95_ns",
+        "p99_ns",
+        "low_1_percent_fps",
+        "low_0_1_percent_fps",
+        "std_dev_ns",
+        "jank",
+        "big_jank",
+    ];
+
+    fn write_csv(&self, line: &mut String) {
+        let _ = write!(
+            line,
+            "{},{},{},{},",
+            self.timestamp_ns, self.pid, self.frames, self.duration_ns
+        );
+        push_float(line, self.average_fps);
+        let _ = write!(
+            line,
+            ",{},{},{},{},{},{},",
+            self.min_ns, self.max_ns, self.median_ns, self.p90_ns, self.p95_ns, self.p99_ns
+        );
+        push_float(line, self.low_1_percent_fps);
+        line.push(',');
+        push_float(line, self.low_0_1_percent_fps);
+        let _ = write!(line, ",{},{},{}", self.std_dev_ns, self.jank, self.big_jank);
+    }
+}
+
+/// Write [`Record`]s as CSV, with a header row of the [columns](Record::COLUMNS)
+///
+/// Every row starts with a `schema_version` column, the [`SCHEMA_VERSION`].
+/// Empty fields are `None`, or a float that is not finite
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use frame_analyzer::{
+///     FrameEvent,
+///     export::{CsvWriter, FrameRecord},
+///     jank::JankKind,
+/// };
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let mut writer = CsvWriter::new(Vec::new())?;
+/// let event = FrameEvent::new(42, 0x7f00, 1_000_000_000, Duration::from_millis(40));
+/// writer.write(&FrameRecord::new(&event, JankKind::BigJank))?;
+///
+/// let csv = String::from_utf8(writer.finish()?)?;
+/// assert_eq!(
+///     csv,
+///     "schema_version,timestamp_ns,pid,surface,frametime_ns,refresh_period_ns,vsyncs,jank,big_jank\n\
+///      1,1000000000,42,32512,40000000,,,false,true\n"
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub struct CsvWriter<W: Write, R: Record> {
+    inner: W,
+    line: String,
+    record: PhantomData<R>,
+}
+
+impl<R: Record> CsvWriter<BufWriter<File>, R> {
+    /// Create a CSV file at `path`, truncating it if it exists
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if the file cannot be created or written
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::create(path)?;
+        Self::new(BufWriter::new(file))
+    }
+}
+
+impl<W: Write, R: Record> CsvWriter<W, R> {
+    /// Write CSV to any writer, the header row is written immediately
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if writing fails
+    pub fn new(mut inner: W) -> Result<Self> {
+        writeln!(inner, "schema_version,{}", R::COLUMNS.join(","))?;
+
+        Ok(Self {
+            inner,
+            line: String::new(),
+            record: PhantomData,
+        })
+    }
+
+    /// Write a row
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if writing fails
+    pub fn write(&mut self, record: &R) -> Result<()> {
+        self.line.clear();
+        let _ = write!(self.line, "{SCHEMA_VERSION},");
+        record.write_csv(&mut self.line);
+        self.line.push('\n');
+
+        self.inner.write_all(self.line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Flush the rows
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if writing fails
+    pub fn flush(&mut self) -> Result<()> {
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// Flush the rows and return the inner writer
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if writing fails
+    pub fn finish(mut self) -> Result<W> {
+        self.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Write [`Record`]s as JSON Lines, one [`Versioned`] object per line
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use frame_analyzer::{
+///     FrameEvent,
+///     export::{FrameRecord, JsonLinesWriter},
+///     jank::JankKind,
+/// };
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let mut writer = JsonLinesWriter::new(Vec::new());
+/// for i in 1..=3 {
+///     let event = FrameEvent::new(42, 0x7f00, i * 16_000_000, Duration::from_millis(16));
+///     writer.write(&FrameRecord::new(&event, JankKind::Smooth))?;
+/// }
+///
+/// let jsonl = String::from_utf8(writer.finish()?)?;
+/// assert!(jsonl.starts_with(r#"{"schema_version":1,"timestamp_ns":16000000,"#));
+/// let records = jsonl
+///     .lines()
+///     .map(serde_json::from_str)
+///     .collect::<Result<Vec<FrameRecord>, _>>()?;
+/// assert_eq!(records.len(), 3);
+/// assert_eq!(records[2].timestamp_ns, 48_000_000);
+/// # Ok(())
+/// # }
+/// ```
+pub struct JsonLinesWriter<W: Write> {
+    inner: W,
+}
+
+impl JsonLinesWriter<BufWriter<File>> {
+    /// Create a JSON Lines file at `path`, truncating it if it exists
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if the file cannot be created
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    /// Write JSON Lines to any writer
+    pub const fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    /// Write a record as a line
+    ///
+    /// # Errors
+    ///
+    /// - `IOError` if writing fails
+    /// - `SerdeError` if the record fails to serialize
+    pub fn write<R: Record>(&mut self, record: &R) -> Result<()> {
+        let versioned = Versioned {
+            schema_version: SCHEMA_VERSION,
+            record,
+        };
+        serde_json::to_writer(&mut self.inner, &versioned)?;
+        self.inner.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Flush the lines
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if writing fails
+    pub fn flush(&mut self) -> Result<()> {
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// Flush the lines and return the inner writer
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if writing fails
+    pub fn finish(mut self) -> Result<W> {
+        self.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Push an optional CSV field, empty if `None`
+fn push_optional<T: Display>(line: &mut String, field: Option<T>) {
+    if let Some(field) = field {
+        let _ = write!(line, "{field}");
+    }
+}
+
+/// Push a float CSV field, empty if it is not finite like in JSON
+fn push_float(line: &mut String, field: f64) {
+    if field.is_finite() {
+        let _ = write!(line, "{field}");
+    }
+}
+
+const fn nanos(duration: Duration) -> u64 {
+    duration.as_nanos() as u64
+}
@@ -0,0 +1,92 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use mio::Waker;
+
+/// Wakes a blocked receive or stops [`Analyzer::run`](crate::Analyzer::run), from another thread or a signal handler
+///
+/// Cancelling stops the running loop, or the next one if none is running; the loop clears the request when it returns
+///
+/// # Examples
+///
+/// ```
+/// use std::{thread, time::Duration};
+///
+/// use frame_analyzer::{Analyzer, RecvOutcome, source::MemorySource};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// // a source which never ends
+/// let (source, _sender) = MemorySource::new()?;
+/// let mut analyzer = Analyzer::with_source(source)?;
+///
+/// let handle = analyzer.handle();
+/// thread::spawn(move || {
+///     thread::sleep(Duration::from_millis(100));
+///     handle.wake();
+///     thread::sleep(Duration::from_millis(100));
+///     handle.cancel();
+/// });
+///
+/// assert_eq!(analyzer.recv_result(None)?, RecvOutcome::Interrupted);
+/// analyzer.run()?; // returns once cancelled
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AnalyzerHandle {
+    cancelled: Arc<AtomicBool>,
+    waker: Arc<Waker>,
+}
+
+impl AnalyzerHandle {
+    pub(crate) fn new(waker: Waker) -> Self {
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            waker: Arc::new(waker),
+        }
+    }
+
+    /// Make the blocked receive return [`RecvOutcome::Interrupted`](crate::RecvOutcome::Interrupted), or the next one if none is blocked
+    ///
+    /// `recv` & `recv_timeout` return `None`
+    pub fn wake(&self) {
+        let _ = self.waker.wake();
+    }
+
+    /// Stop the [`Analyzer::run`](crate::Analyzer::run) loop, waking it if it is blocked
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+        self.wake();
+    }
+
+    /// Whether a cancellation is pending, it is cleared as the loop returns
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Clear the pending cancellation, returns whether there was one
+    pub(crate) fn take_cancel(&self) -> bool {
+        self.cancelled.swap(false, Ordering::AcqRel)
+    }
+}
@@ -0,0 +1,167 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{ops::Range, time::Duration};
+
+use frame_analyzer_ebpf_common::{
+    HISTOGRAM_BINS, HISTOGRAM_LINEAR_BINS, HISTOGRAM_LINEAR_WIDTH_NS, histogram_bin,
+};
+
+/// Frametime histogram of an application, collected by the ebpf program in [`FrameMode::Histogram`](crate::FrameMode::Histogram)
+///
+/// The bins are 1ms wide up to 32ms, then double in width (`[32ms, 64ms)`, `[64ms, 128ms)`...), the last bin is unbounded
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use frame_analyzer::Histogram;
+///
+/// let mut histogram = Histogram::new();
+/// histogram.record(Duration::from_micros(16_667));
+/// histogram.record(Duration::from_micros(16_900));
+/// histogram.record(Duration::from_millis(50));
+///
+/// assert_eq!(histogram.count(), 3);
+/// assert_eq!(histogram.bin_range(16), Duration::from_millis(16)..Duration::from_millis(17));
+/// assert_eq!(histogram.percentile(50.0), Some(Duration::from_millis(17)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Histogram {
+    bins: [u64; HISTOGRAM_BINS],
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    /// Number of bins of a histogram
+    pub const BINS: usize = HISTOGRAM_BINS;
+
+    /// Create an empty histogram
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            bins: [0; HISTOGRAM_BINS],
+        }
+    }
+
+    pub(crate) const fn from_bins(bins: &[u64; HISTOGRAM_BINS]) -> Self {
+        Self { bins: *bins }
+    }
+
+    /// Count a frametime into its bin, the same way the ebpf program does
+    pub const fn record(&mut self, frametime: Duration) {
+        self.bins[histogram_bin(frametime.as_nanos() as u64)] += 1;
+    }
+
+    /// Add up the counts of another histogram
+    pub fn merge(&mut self, other: &Self) {
+        for (bin, count) in self.bins.iter_mut().zip(other.bins) {
+            *bin += count;
+        }
+    }
+
+    /// The frame counts of every bin
+    #[must_use]
+    pub const fn bins(&self) -> &[u64; HISTOGRAM_BINS] {
+        &self.bins
+    }
+
+    /// The total number of frames
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.bins.iter().sum()
+    }
+
+    /// Whether no frame was recorded
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+
+    /// The frametime range covered by a bin, the end of the last bin is [`Duration::MAX`]
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bin >= Histogram::BINS`
+    #[must_use]
+    pub const fn bin_range(&self, bin: usize) -> Range<Duration> {
+        assert!(bin < HISTOGRAM_BINS);
+
+        let start = bin_start(bin);
+        let end = if bin + 1 < HISTOGRAM_BINS {
+            bin_start(bin + 1)
+        } else {
+            Duration::MAX
+        };
+
+        start..end
+    }
+
+    /// An iterator over the non-empty bins, as `(range, count)`
+    pub fn iter(&self) -> impl Iterator<Item = (Range<Duration>, u64)> + '_ {
+        self.bins
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(bin, count)| (self.bin_range(bin), *count))
+    }
+
+    /// Estimate a percentile (`0.0..=100.0`) of the frametimes, as the upper bound of the bin it falls into
+    ///
+    /// The start of the bin is returned for the unbounded last bin, `None` if the histogram is empty
+    #[must_use]
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+
+        let rank = ((percentile.clamp(0.0, 100.0) / 100.0) * count as f64).ceil() as u64;
+        let rank = rank.max(1);
+
+        let mut seen = 0;
+        for (bin, bin_count) in self.bins.iter().enumerate() {
+            seen += bin_count;
+            if seen >= rank {
+                let range = self.bin_range(bin);
+                return Some(if range.end == Duration::MAX {
+                    range.start
+                } else {
+                    range.end
+                });
+            }
+        }
+
+        None
+    }
+}
+
+const fn bin_start(bin: usize) -> Duration {
+    if bin < HISTOGRAM_LINEAR_BINS {
+        Duration::from_nanos(bin as u64 * HISTOGRAM_LINEAR_WIDTH_NS)
+    } else {
+        let shift = bin - HISTOGRAM_LINEAR_BINS;
+        Duration::from_nanos((HISTOGRAM_LINEAR_BINS as u64 * HISTOGRAM_LINEAR_WIDTH_NS) << shift)
+    }
+}
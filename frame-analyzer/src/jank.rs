@@ -0,0 +1,355 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Jank detection on top of the frame stream
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use frame_analyzer::{
+//!     jank::{JankDetector, JankKind, JankRule},
+//!     stats::Window,
+//! };
+//!
+//! let vsync = Duration::from_micros(16_667);
+//! let mut detector = JankDetector::new(JankRule::vsync(vsync), Window::Frames(120));
+//!
+//! assert_eq!(detector.push_frametime(vsync), JankKind::Smooth);
+//! assert_eq!(detector.push_frametime(vsync * 2 - Duration::from_millis(1)), JankKind::Jank);
+//! assert_eq!(detector.push_frametime(vsync * 3), JankKind::BigJank);
+//!
+//! let counts = detector.counts();
+//! assert_eq!((counts.frames, counts.jank, counts.big_jank), (3, 1, 1));
+//! ```
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{FrameEvent, stats::Window, target_fps::TargetFpsEstimate};
+
+/// Two frames of a 24 fps movie, the PerfDog threshold of a jank
+const PERFDOG_JANK: Duration = Duration::from_nanos(83_333_333);
+/// Three frames of a 24 fps movie, the PerfDog threshold of a big jank
+const PERFDOG_BIG_JANK: Duration = Duration::from_millis(125);
+/// Number of previous frames averaged by the PerfDog rule
+const PERFDOG_HISTORY: usize = 3;
+
+/// How a frame is classified by a [`JankDetector`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum JankKind {
+    /// The frame is not janky
+    #[default]
+    Smooth,
+    /// The frame is janky
+    Jank,
+    /// The frame is janky beyond the big jank threshold, not counted as [`JankKind::Jank`]
+    BigJank,
+}
+
+impl JankKind {
+    /// Whether the frame is [`JankKind::Jank`] or [`JankKind::BigJank`]
+    #[must_use]
+    pub const fn is_janky(self) -> bool {
+        !matches!(self, Self::Smooth)
+    }
+}
+
+/// The rule classifying frames
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum JankRule {
+    /// Android-style, relative to the display refresh period
+    ///
+    /// A frame longer than `jank_factor` refresh periods is a jank, longer than `big_jank_factor` refresh periods a big jank
+    Vsync {
+        /// The display refresh period
+        period: Duration,
+        /// Threshold of a jank, in refresh periods
+        jank_factor: f64,
+        /// Threshold of a big jank, in refresh periods
+        big_jank_factor: f64,
+    },
+    /// PerfDog-style, relative to the previous three frames
+    ///
+    /// A frame longer than twice the average of the previous three frames is a jank if it is also longer than two frames of a 24 fps movie (83.3ms),
+    /// a big jank if it is longer than three (125ms)
+    PerfDog,
+}
+
+impl JankRule {
+    /// [`JankRule::Vsync`] with a frame janky beyond 1.5 refresh periods and big janky beyond 2.5,
+    /// i.e. at least one and two missed vsyncs.
+    /// [`JankRule::PerfDog`] if `period` is zero, every frame would be infinitely many periods long
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use frame_analyzer::jank::JankRule;
+    ///
+    /// assert_eq!(JankRule::vsync(Duration::ZERO), JankRule::PerfDog);
+    /// ```
+    #[must_use]
+    pub const fn vsync(period: Duration) -> Self {
+        if period.is_zero() {
+            return Self::PerfDog;
+        }
+
+        Self::Vsync {
+            period,
+            jank_factor: 1.5,
+            big_jank_factor: 2.5,
+        }
+    }
+
+    /// The rule of a frame-rate-capped app: [`JankRule::vsync`] against the longer of the refresh period and the frame
+    /// interval of the cap, so a 30 fps game on a 60Hz display is not janky on every frame.
+    /// [`JankRule::PerfDog`] until both are known and non-zero
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use frame_analyzer::{jank::JankRule, target_fps::TargetFpsEstimate};
+    ///
+    /// let vsync = Duration::from_micros(16_667);
+    /// let cap = TargetFpsEstimate { fps: 30, confidence: 1.0 };
+    /// assert_eq!(JankRule::capped(Some(vsync), Some(cap)), JankRule::vsync(cap.interval()));
+    /// assert_eq!(JankRule::capped(Some(vsync), None), JankRule::PerfDog);
+    /// assert_eq!(JankRule::capped(Some(Duration::ZERO), Some(cap)), JankRule::PerfDog);
+    /// ```
+    #[must_use]
+    pub fn capped(refresh_period: Option<Duration>, cap: Option<TargetFpsEstimate>) -> Self {
+        match (refresh_period, cap) {
+            (Some(period), Some(cap)) if !period.is_zero() && cap.fps != 0 => {
+                Self::vsync(cap.interval().max(period))
+            }
+            _ => Self::PerfDog,
+        }
+    }
+}
+
+/// Jank counts of a [`JankDetector`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JankCounts {
+    /// Number of frames
+    pub frames: usize,
+    /// Number of [`JankKind::Jank`] frames
+    pub jank: usize,
+    /// Number of [`JankKind::BigJank`] frames
+    pub big_jank: usize,
+}
+
+impl JankCounts {
+    /// Number of janky frames, including the big janks
+    #[must_use]
+    pub const fn janky(&self) -> usize {
+        self.jank + self.big_jank
+    }
+
+    /// Fraction of janky frames, `0.0` if there is no frame
+    #[must_use]
+    pub fn jank_rate(&self) -> f64 {
+        if self.frames == 0 {
+            0.0
+        } else {
+            self.janky() as f64 / self.frames as f64
+        }
+    }
+
+    const fn add(&mut self, kind: JankKind) {
+        self.frames += 1;
+        match kind {
+            JankKind::Smooth => (),
+            JankKind::Jank => self.jank += 1,
+            JankKind::BigJank => self.big_jank += 1,
+        }
+    }
+
+    const fn sub(&mut self, kind: JankKind) {
+        self.frames -= 1;
+        match kind {
+            JankKind::Smooth => (),
+            JankKind::Jank => self.jank -= 1,
+            JankKind::BigJank => self.big_jank -= 1,
+        }
+    }
+}
+
+/// Classify the frames of one frame stream (e.g. one pid) by a [`JankRule`], and count them over a sliding [`Window`]
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use frame_analyzer::{
+///     jank::{JankDetector, JankKind, JankRule},
+///     stats::Window,
+/// };
+///
+/// let mut detector = JankDetector::new(JankRule::PerfDog, Window::Duration(Duration::from_secs(10)));
+/// for _ in 0..3 {
+///     detector.push_frametime(Duration::from_millis(33));
+/// }
+///
+/// // twice the average of the previous three frames, but shorter than two movie frames
+/// assert_eq!(detector.push_frametime(Duration::from_millis(70)), JankKind::Smooth);
+/// assert_eq!(detector.push_frametime(Duration::from_millis(150)), JankKind::BigJank);
+/// assert_eq!(detector.total().janky(), 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct JankDetector {
+    rule: JankRule,
+    window: Window,
+    history: VecDeque<Duration>,
+    frames: VecDeque<(Duration, JankKind)>,
+    window_duration: Duration,
+    counts: JankCounts,
+    total: JankCounts,
+}
+
+impl JankDetector {
+    /// Create a detector
+    #[must_use]
+    pub fn new(rule: JankRule, window: Window) -> Self {
+        Self {
+            rule,
+            window,
+            history: VecDeque::with_capacity(PERFDOG_HISTORY),
+            frames: VecDeque::new(),
+            window_duration: Duration::ZERO,
+            counts: JankCounts::default(),
+            total: JankCounts::default(),
+        }
+    }
+
+    /// The rule of this detector
+    #[must_use]
+    pub const fn rule(&self) -> JankRule {
+        self.rule
+    }
+
+    /// Replace the rule, e.g. when the display refresh rate changes, the counted frames are kept
+    pub const fn set_rule(&mut self, rule: JankRule) {
+        self.rule = rule;
+    }
+
+    /// Classify a frame and count it
+    pub fn push(&mut self, event: &FrameEvent) -> JankKind {
+        self.push_frametime(event.frametime)
+    }
+
+    /// Classify a raw frametime and count it
+    pub fn push_frametime(&mut self, frametime: Duration) -> JankKind {
+        let kind = self.classify(frametime);
+
+        if self.history.len() >= PERFDOG_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(frametime);
+
+        self.total.add(kind);
+        self.counts.add(kind);
+        self.frames.push_back((frametime, kind));
+        self.window_duration += frametime;
+
+        match self.window {
+            Window::Frames(frames) => {
+                while self.frames.len() > frames.max(1) {
+                    self.pop_oldest();
+                }
+            }
+            Window::Duration(duration) => {
+                while self.window_duration > duration && self.frames.len() > 1 {
+                    self.pop_oldest();
+                }
+            }
+        }
+
+        kind
+    }
+
+    fn pop_oldest(&mut self) {
+        if let Some((frametime, kind)) = self.frames.pop_front() {
+            self.window_duration -= frametime;
+            self.counts.sub(kind);
+        }
+    }
+
+    fn classify(&self, frametime: Duration) -> JankKind {
+        match self.rule {
+            // A zero period built by hand is classified as `PerfDog`, see `JankRule::vsync`
+            JankRule::Vsync {
+                period,
+                jank_factor,
+                big_jank_factor,
+            } if !period.is_zero() => {
+                let periods = frametime.as_secs_f64() / period.as_secs_f64();
+                if periods > big_jank_factor {
+                    JankKind::BigJank
+                } else if periods > jank_factor {
+                    JankKind::Jank
+                } else {
+                    JankKind::Smooth
+                }
+            }
+            JankRule::Vsync { .. } | JankRule::PerfDog => {
+                if self.history.len() < PERFDOG_HISTORY {
+                    return JankKind::Smooth;
+                }
+
+                let average = self.history.iter().sum::<Duration>() / PERFDOG_HISTORY as u32;
+                if frametime <= average * 2 {
+                    JankKind::Smooth
+                } else if frametime > PERFDOG_BIG_JANK {
+                    JankKind::BigJank
+                } else if frametime > PERFDOG_JANK {
+                    JankKind::Jank
+                } else {
+                    JankKind::Smooth
+                }
+            }
+        }
+    }
+
+    /// Jank counts of the frames in the window
+    #[must_use]
+    pub const fn counts(&self) -> JankCounts {
+        self.counts
+    }
+
+    /// Jank counts of all frames since the detector was created or [`JankDetector::reset`]
+    #[must_use]
+    pub const fn total(&self) -> JankCounts {
+        self.total
+    }
+
+    /// Drop all frames and counts
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.frames.clear();
+        self.window_duration = Duration::ZERO;
+        self.counts = JankCounts::default();
+        self.total = JankCounts::default();
+    }
+}
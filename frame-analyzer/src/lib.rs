@@ -21,7 +21,8 @@
     clippy::module_name_repetitions,
     clippy::cast_possible_wrap,
     clippy::cast_sign_loss,
-    clippy::cast_possible_truncation
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss
 )]
 //! # frame-analyzer
 //!
@@ -30,16 +31,16 @@
 //! - This IS NOT a bin crate, it uses some tricks (see [source](https://github.com/shadow3aaa/frame-analyzer-ebpf?tab=readme-ov-file)) to get it to work like a normal lib crate, even though it includes an EBPF program
 //! - Only 64-bit devices & apps are supported!
 //!
+//! # Features
+//!
+//! - `serde`: `Serialize` & `Deserialize` for the event and statistics types, CSV & JSON Lines exporters in [`export`]
+//! - `metrics-server`: an HTTP endpoint exposing the frame metrics to Prometheus, see `metrics`
+//!
 //! # Examples
 //!
 //! Simple frametime analyzer, print pid & frametime on the screen
 //!
 //! ```
-//! use std::sync::{
-//!   atomic::{AtomicBool, Ordering},
-//!   Arc,
-//! };
-//!
 //! use frame_analyzer::Analyzer;
 //!
 //! # fn main() {
@@ -55,48 +56,75 @@
 //! analyzer.attach_app(app_pid_b)?;
 //! analyzer.attach_app(app_pid_c)?; // muti-apps are supported
 //!
-//! let running = Arc::new(AtomicBool::new(true));
-//!
-//! {
-//!     let running = running.clone();
-//!     ctrlc::set_handler(move || {
-//!         running.store(false, Ordering::Release);
-//!     })?;
-//! }
+//! let handle = analyzer.handle();
+//! ctrlc::set_handler(move || handle.cancel())?;
 //! #
-//! #   running.store(false, Ordering::Release); // avoid dead-loop in test
+//! #   analyzer.handle().cancel(); // avoid dead-loop in test
 //! #
-//! while running.load(Ordering::Acquire) {
-//!     if let Some((pid, frametime)) = analyzer.recv() {
-//!         println!("process: {pid}, frametime: {frametime:?}");
-//!     }
-//! }
+//! analyzer.run_with(|event| {
+//!     println!("process: {}, frametime: {:?}", event.pid, event.frametime);
+//! })?;
 //! #
 //! #   Ok(())
 //! # }
 //! ```
 mod analyze_target;
+mod builder;
+pub mod compare;
+pub mod daemon;
 mod ebpf;
 mod error;
+mod event;
+pub mod export;
+mod handle;
+mod histogram;
+pub mod jank;
+#[cfg(feature = "metrics-server")]
+pub mod metrics;
+mod observer;
+pub mod pacing;
+pub mod refresh;
+pub mod replay;
+pub mod report;
+pub mod source;
+pub mod stats;
+pub mod synthetic;
+pub mod target_fps;
+pub mod trace;
 mod uprobe;
 
 use std::{
     collections::{HashMap, VecDeque},
-    os::unix::io::AsRawFd,
-    time::Duration,
+    io, mem,
+    path::Path,
+    time::{Duration, Instant},
 };
 
-use mio::{Events, Interest, Poll, Token, event::Event, unix::SourceFd};
+pub use frame_analyzer_ebpf_common::FrameSignal;
+use mio::{Events, Poll, Token, Waker};
 
 use analyze_target::AnalyzeTarget;
+pub use builder::{AnalyzerBuilder, Backend, FrameMode};
 pub use error::AnalyzerError;
 use error::Result;
+pub use event::{FrameEvent, RecvOutcome};
+pub use handle::AnalyzerHandle;
+pub use histogram::Histogram;
+pub use observer::{FrameObserver, ObserverId};
+use refresh::{RefreshRateProvider, RefreshTracker};
+use replay::ReplaySource;
+use source::{FrameSource, Sources};
+use trace::{TraceRecord, TraceWriter};
 use uprobe::UprobeHandler;
 
 /// The pid of the target application
 pub type Pid = i32;
 
 const EVENT_MAX: usize = 1024;
+/// Token of the waker of the [`AnalyzerHandle`], next to [`source::GLOBAL_TOKEN`]
+const WAKE_TOKEN: Token = Token(usize::MAX - 1);
+/// How often [`Analyzer::run`] checks whether the attached apps are still alive
+const EXIT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 /// The Frame Analyzer
 ///
@@ -122,9 +150,22 @@ const EVENT_MAX: usize = 1024;
 /// # }
 /// ```
 pub struct Analyzer {
-    poll: Option<Poll>,
+    poll: Poll,
+    backend: Backend,
+    frame_mode: FrameMode,
+    sources: Sources,
+    refresh: RefreshTracker,
+    recorder: Option<TraceWriter>,
     map: HashMap<Pid, AnalyzeTarget>,
-    buffer: VecDeque<Pid>,
+    pending: VecDeque<FrameSignal>,
+    observers: Vec<(ObserverId, Box<dyn FrameObserver>)>,
+    next_observer: u64,
+    handle: AnalyzerHandle,
+    /// Woken along with frames, reported once they are received
+    woken: bool,
+    /// An error of a signal whose outcome was already returned, reported by the next receive
+    error: Option<AnalyzerError>,
+    exit_check: Instant,
 }
 
 impl Analyzer {
@@ -150,11 +191,128 @@ impl Analyzer {
     /// # }
     /// ```
     pub fn new() -> Result<Self> {
-        let poll = None;
+        Self::builder().build()
+    }
+
+    /// Create a [`AnalyzerBuilder`] to configure the analyzer, e.g. select the [`Backend`]
+    ///
+    /// # Examples
+    /// ```
+    /// use frame_analyzer::{Analyzer, Backend};
+    ///
+    /// #
+    /// # fn main() {
+    /// #   let _ = try_main();
+    /// # }
+    /// #
+    /// # fn try_main() -> anyhow::Result<()> {
+    /// let analyzer = Analyzer::builder().backend(Backend::Global).build()?;
+    /// #   Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn builder() -> AnalyzerBuilder {
+        AnalyzerBuilder::new()
+    }
+
+    pub(crate) fn from_builder(builder: &AnalyzerBuilder) -> Result<Self> {
+        let poll = Poll::new()?;
+        let handle = AnalyzerHandle::new(Waker::new(poll.registry(), WAKE_TOKEN)?);
         let map = HashMap::new();
-        let buffer = VecDeque::with_capacity(EVENT_MAX);
+        let pending = VecDeque::with_capacity(EVENT_MAX);
+
+        Ok(Self {
+            poll,
+            backend: builder.backend,
+            frame_mode: builder.frame_mode,
+            sources: Sources::uprobe(),
+            refresh: RefreshTracker::new(),
+            recorder: None,
+            map,
+            pending,
+            observers: Vec::new(),
+            next_observer: 0,
+            handle,
+            woken: false,
+            error: None,
+            exit_check: Instant::now(),
+        })
+    }
+
+    /// Create an analyzer fed by a recorded trace instead of the ebpf uprobes, see [`replay`]
+    ///
+    /// Apps still have to be attached to receive their frames, attaching does not need any privilege.
+    /// The refresh period is taken from the metadata of the trace if it was recorded
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Analyzer::new`]
+    ///
+    /// # Examples
+    /// ```
+    /// use frame_analyzer::{
+    ///     Analyzer,
+    ///     replay::{ReplaySource, ReplaySpeed},
+    /// };
+    ///
+    /// # fn main() {
+    /// #   let _ = try_main();
+    /// # }
+    /// #
+    /// # fn try_main() -> anyhow::Result<()> {
+    /// #   let app_pid = 2;
+    /// let replay = ReplaySource::open("/data/local/tmp/session.fatrace", ReplaySpeed::RealTime)?;
+    /// let mut analyzer = Analyzer::with_replay(replay)?;
+    /// analyzer.attach_app(app_pid)?;
+    ///
+    /// while let Some(event) = analyzer.recv_event() {
+    ///     println!("process: {}, frametime: {:?}", event.pid, event.frametime);
+    /// }
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn with_replay(replay: ReplaySource) -> Result<Self> {
+        let period = replay
+            .metadata()
+            .refresh_rate
+            .filter(|hz| *hz > 0.0)
+            .map(|hz| Duration::from_secs_f64(1.0 / hz));
+        let mut analyzer = Self::with_source(replay)?;
+        analyzer.set_refresh_period(period);
+
+        Ok(analyzer)
+    }
 
-        Ok(Self { poll, map, buffer })
+    /// Create an analyzer fed by any [`FrameSource`] instead of the ebpf uprobes
+    ///
+    /// Apps still have to be attached to receive their frames, attaching does not need any privilege
+    ///
+    /// # Errors
+    ///
+    /// - `IOError` if the poll cannot be created
+    /// - Any error of [`FrameSource::raw_fd`]
+    ///
+    /// # Examples
+    /// ```
+    /// use frame_analyzer::{Analyzer, FrameSignal, source::MemorySource};
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let signals = (0..=60).map(|i| FrameSignal::new(i * 16_666_667, 0x7f00, 0, 42));
+    /// let mut analyzer = Analyzer::with_source(MemorySource::from_signals(signals)?)?;
+    /// analyzer.attach_app(42)?;
+    ///
+    /// let (pid, frametime) = analyzer.recv().unwrap();
+    /// assert_eq!(pid, 42);
+    /// assert_eq!(frametime.as_nanos(), 16_666_667);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_source<S: FrameSource + 'static>(source: S) -> Result<Self> {
+        let mut analyzer = Self::from_builder(&AnalyzerBuilder::new())?;
+        analyzer.sources = Sources::External(Box::new(source));
+        analyzer.register_poll()?;
+
+        Ok(analyzer)
     }
 
     /// Attach the Analyzer to the target application
@@ -189,8 +347,26 @@ impl Analyzer {
             return Ok(());
         }
 
-        let uprobe = UprobeHandler::attach_app(pid)?;
-        self.map.insert(pid, AnalyzeTarget::new(uprobe));
+        if let Sources::Uprobe { per_app, global } = &mut self.sources {
+            match self.backend {
+                Backend::PerApp => {
+                    per_app.insert(
+                        pid,
+                        UprobeHandler::attach_app(pid, self.frame_mode.flags())?,
+                    );
+                }
+                Backend::Global => {
+                    let global = match global {
+                        Some(global) => global,
+                        None => global.insert(Box::new(UprobeHandler::attach_global(
+                            self.frame_mode.flags(),
+                        )?)),
+                    };
+                    global.pid_filter()?.insert(pid as u32, 0, 0)?;
+                }
+            }
+        }
+        self.map.insert(pid, AnalyzeTarget::new());
         self.register_poll()?;
 
         Ok(())
@@ -227,7 +403,14 @@ impl Analyzer {
         }
 
         self.map.remove(&pid).ok_or(AnalyzerError::AppNotFound)?;
-        self.buffer.retain(|pid_event| *pid_event != pid);
+        if let Sources::Uprobe { per_app, global } = &mut self.sources {
+            per_app.remove(&pid);
+            if let Some(global) = global {
+                global.pid_filter()?.remove(&(pid as u32))?;
+                global.forget(pid as u32)?;
+            }
+        }
+        self.pending.retain(|signal| signal.pid as Pid != pid);
         self.register_poll()?;
 
         Ok(())
@@ -256,7 +439,11 @@ impl Analyzer {
     /// ```
     pub fn detach_apps(&mut self) {
         self.map.clear();
-        self.buffer.clear();
+        if let Sources::Uprobe { per_app, global } = &mut self.sources {
+            per_app.clear();
+            *global = None;
+        }
+        self.pending.clear();
     }
 
     /// Attempts to wait for a frametime value on this analyzer
@@ -285,21 +472,7 @@ impl Analyzer {
     /// # }
     /// ```
     pub fn recv(&mut self) -> Option<(Pid, Duration)> {
-        if self.buffer.is_empty() {
-            if let Some(ref mut poll) = self.poll {
-                let mut events = Events::with_capacity(EVENT_MAX);
-                let _ = poll.poll(&mut events, None);
-
-                self.buffer.extend(events.iter().map(event_to_pid));
-            }
-
-            let _ = self.register_poll();
-        }
-
-        let pid = self.buffer.pop_front()?;
-        let frametime = self.map.get_mut(&pid)?.update()?;
-
-        Some((pid, frametime))
+        self.recv_event().map(|event| (event.pid, event.frametime))
     }
 
     /// Attempts to wait for a value on this receiver, returning `None` if it waits more than timeout
@@ -329,21 +502,489 @@ impl Analyzer {
     /// # }
     /// ```
     pub fn recv_timeout(&mut self, time: Duration) -> Option<(Pid, Duration)> {
-        if self.buffer.is_empty() {
-            if let Some(ref mut poll) = self.poll {
-                let mut events = Events::with_capacity(EVENT_MAX);
-                let _ = poll.poll(&mut events, Some(time));
+        self.recv_event_timeout(time)
+            .map(|event| (event.pid, event.frametime))
+    }
+
+    /// Like [`Analyzer::recv`], but returns the whole [`FrameEvent`] with the surface & timestamp of the frame
+    ///
+    /// # Examples
+    /// ```
+    /// # use frame_analyzer::Analyzer;
+    /// #
+    /// # fn main() {
+    /// #   let _ = try_main();
+    /// # }
+    /// #
+    /// # fn try_main() -> anyhow::Result<()> {
+    /// #   let mut analyzer = Analyzer::new()?;
+    /// #   let app_pid = 2;
+    /// analyzer.attach_app(app_pid)?;
+    ///
+    /// if let Some(event) = analyzer.recv_event() {
+    ///     println!("process: {}, frametime: {:?}, at: {}ns", event.pid, event.frametime, event.timestamp_ns);
+    /// }
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn recv_event(&mut self) -> Option<FrameEvent> {
+        self.poll_event(None)
+    }
+
+    /// Like [`Analyzer::recv_timeout`], but returns the whole [`FrameEvent`] with the surface & timestamp of the frame
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    /// # use frame_analyzer::Analyzer;
+    ///
+    /// # fn main() {
+    /// #   let _ = try_main();
+    /// # }
+    /// #
+    /// # fn try_main() -> anyhow::Result<()> {
+    /// #   let mut analyzer = Analyzer::new()?;
+    /// #   let app_pid = 2;
+    /// analyzer.attach_app(app_pid)?;
+    ///
+    /// if let Some(event) = analyzer.recv_event_timeout(Duration::from_secs(1)) {
+    ///     println!("process: {}, frametime: {:?}", event.pid, event.frametime);
+    /// }
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn recv_event_timeout(&mut self, time: Duration) -> Option<FrameEvent> {
+        self.poll_event(Some(time))
+    }
+
+    /// Wait for a frame until `timeout`, or forever if `None`, telling why no frame was received
+    ///
+    /// Returns a [`RecvOutcome::Frame`], [`RecvOutcome::Timeout`], [`RecvOutcome::Interrupted`] or [`RecvOutcome::Exhausted`].
+    /// Unlike [`Analyzer::recv_event_timeout`], signals which do not produce a frame do not end the wait,
+    /// e.g. the frames of a surface which is not selected, see [`Analyzer::try_recv`] to observe them
+    ///
+    /// # Errors
+    ///
+    /// - `IOError` if polling the sources fails
+    /// - Any error of [`FrameSource::drain`], the signals drained before it are still received by the next calls
+    /// - Any error of the [recorder](Analyzer::start_recording) or, with `suppress_unselected`, of selecting the surface in the ebpf program.
+    ///   Its frame is still returned, the error is returned by the next call
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use frame_analyzer::{Analyzer, FrameSignal, RecvOutcome, source::MemorySource};
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let (source, sender) = MemorySource::new()?;
+    /// let mut analyzer = Analyzer::with_source(source)?;
+    /// analyzer.attach_app(42)?;
+    ///
+    /// sender.send(FrameSignal::new(0, 0x7f00, 0, 42));
+    /// let timeout = Some(Duration::from_millis(10));
+    /// // the first signal of a surface has no frametime
+    /// assert_eq!(analyzer.recv_result(timeout)?, RecvOutcome::Timeout);
+    ///
+    /// sender.send(FrameSignal::new(16_000_000, 0x7f00, 0, 42));
+    /// let event = analyzer.recv_result(timeout)?.frame().unwrap();
+    /// assert_eq!(event.frametime, Duration::from_millis(16));
+    ///
+    /// drop(sender);
+    /// assert_eq!(analyzer.recv_result(None)?, RecvOutcome::Exhausted);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn recv_result(&mut self, timeout: Option<Duration>) -> Result<RecvOutcome> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        loop {
+            let remaining =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            match self.next_signal(remaining)? {
+                RecvOutcome::Skipped { .. } | RecvOutcome::Detached(_) => {}
+                RecvOutcome::Timeout if remaining.is_none_or(|remaining| !remaining.is_zero()) => {}
+                outcome => return Ok(outcome),
+            }
+        }
+    }
+
+    /// The outcome of the next available signal, without waiting
+    ///
+    /// Every signal is reported, including those which do not produce a frame.
+    /// [`RecvOutcome::Timeout`] means that no signal is available
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Analyzer::recv_result`]
+    ///
+    /// # Examples
+    /// ```
+    /// use frame_analyzer::{Analyzer, FrameSignal, RecvOutcome, source::MemorySource};
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// let (source, sender) = MemorySource::new()?;
+    /// let mut analyzer = Analyzer::with_source(source)?;
+    /// analyzer.attach_app(42)?;
+    /// assert_eq!(analyzer.try_recv()?, RecvOutcome::Timeout);
+    ///
+    /// sender.send(FrameSignal::new(0, 0x7f00, 0, 42));
+    /// sender.send(FrameSignal::new(0, 0x7f00, 0, 7));
+    /// sender.send(FrameSignal::new(16_000_000, 0x7f00, 0, 42));
+    ///
+    /// // the first signal of a surface has no frametime
+    /// assert_eq!(analyzer.try_recv()?, RecvOutcome::Skipped { pid: 42, surface: 0x7f00 });
+    /// assert_eq!(analyzer.try_recv()?, RecvOutcome::Detached(7));
+    /// assert!(matches!(analyzer.try_recv()?, RecvOutcome::Frame(event) if event.pid == 42));
+    /// assert_eq!(analyzer.try_recv()?, RecvOutcome::Timeout);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn try_recv(&mut self) -> Result<RecvOutcome> {
+        self.next_signal(Some(Duration::ZERO))
+    }
+
+    /// Receive frames until [cancelled](AnalyzerHandle::cancel) or [exhausted](Analyzer::is_exhausted), notifying the [observers](Analyzer::add_observer)
+    ///
+    /// Attached apps which exited are detached & reported to [`FrameObserver::on_process_exit`]
+    ///
+    /// # Errors
+    ///
+    /// - Any error of [`Analyzer::recv_result`]
+    /// - `BpfMapError` if detaching an exited app from the global uprobe fails
+    ///
+    /// # Examples
+    /// ```
+    /// # use frame_analyzer::Analyzer;
+    /// use frame_analyzer::FrameEvent;
+    ///
+    /// # fn main() {
+    /// #   let _ = try_main();
+    /// # }
+    /// #
+    /// # fn try_main() -> anyhow::Result<()> {
+    /// #   let app_pid = 2;
+    /// let mut analyzer = Analyzer::new()?;
+    /// analyzer.attach_app(app_pid)?;
+    /// analyzer.add_observer(|event: &FrameEvent| println!("frametime: {:?}", event.frametime));
+    ///
+    /// let handle = analyzer.handle();
+    /// ctrlc::set_handler(move || handle.cancel())?;
+    /// analyzer.run()?;
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn run(&mut self) -> Result<()> {
+        self.run_with(|_| {})
+    }
+
+    /// Like [`Analyzer::run`], also passing every frame to `f`
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Analyzer::run`]
+    ///
+    /// # Examples
+    /// ```
+    /// # use frame_analyzer::Analyzer;
+    /// #
+    /// # fn main() {
+    /// #   let _ = try_main();
+    /// # }
+    /// #
+    /// # fn try_main() -> anyhow::Result<()> {
+    /// #   let app_pid = 2;
+    /// let mut analyzer = Analyzer::new()?;
+    /// analyzer.attach_app(app_pid)?;
+    ///
+    /// let handle = analyzer.handle();
+    /// ctrlc::set_handler(move || handle.cancel())?;
+    /// analyzer.run_with(|event| println!("process: {}, frametime: {:?}", event.pid, event.frametime))?;
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn run_with<F: FnMut(&FrameEvent)>(&mut self, mut f: F) -> Result<()> {
+        while !self.handle.take_cancel() && !self.is_exhausted() {
+            if let RecvOutcome::Frame(event) = self.next_signal(Some(EXIT_CHECK_INTERVAL))? {
+                f(&event);
+            }
+            self.detach_exited()?;
+        }
+
+        Ok(())
+    }
+
+    /// Detach the attached apps which exited, returning their pids
+    ///
+    /// [`Analyzer::run`] does it on its own, call it periodically when receiving the frames otherwise.
+    /// The apps are checked at most once per second, the [observers](Analyzer::add_observer) are notified of every exit.
+    /// Only the ebpf uprobes are checked, the apps of [another source](Analyzer::with_source) never exit
+    ///
+    /// # Errors
+    ///
+    /// `BpfMapError` if detaching an exited app from the global uprobe fails
+    ///
+    /// # Examples
+    /// ```
+    /// # use frame_analyzer::{Analyzer, RecvOutcome};
+    /// # use std::time::Duration;
+    /// #
+    /// # fn main() {
+    /// #   let _ = try_main();
+    /// # }
+    /// #
+    /// # fn try_main() -> anyhow::Result<()> {
+    /// let mut analyzer = Analyzer::new()?;
+    /// #   let app_pid = 2;
+    /// analyzer.attach_app(app_pid)?;
+    ///
+    /// while analyzer.contains(app_pid) {
+    ///     if let RecvOutcome::Frame(event) = analyzer.recv_result(Some(Duration::from_secs(1)))? {
+    ///         println!("frametime: {:?}", event.frametime);
+    ///     }
+    ///     for pid in analyzer.detach_exited()? {
+    ///         println!("{pid} exited");
+    ///     }
+    /// }
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn detach_exited(&mut self) -> Result<Vec<Pid>> {
+        if !matches!(self.sources, Sources::Uprobe { .. })
+            || self.exit_check.elapsed() < EXIT_CHECK_INTERVAL
+        {
+            return Ok(Vec::new());
+        }
+        self.exit_check = Instant::now();
 
-                self.buffer.extend(events.iter().map(event_to_pid));
+        let exited: Vec<_> = self
+            .pids()
+            .filter(|pid| !Path::new(&format!("/proc/{pid}")).exists())
+            .collect();
+        for &pid in &exited {
+            self.detach_app(pid)?;
+            for (_, observer) in &mut self.observers {
+                observer.on_process_exit(pid);
             }
+        }
+
+        Ok(exited)
+    }
+
+    /// A handle to [wake](AnalyzerHandle::wake) a blocked receive or [cancel](AnalyzerHandle::cancel) [`Analyzer::run`],
+    /// it can be cloned & sent to other threads
+    #[must_use]
+    pub fn handle(&self) -> AnalyzerHandle {
+        self.handle.clone()
+    }
+
+    /// Notify `observer` of the frames from now on, see [`FrameObserver`]
+    pub fn add_observer<O: FrameObserver + 'static>(&mut self, observer: O) -> ObserverId {
+        let id = ObserverId::new(self.next_observer);
+        self.next_observer += 1;
+        self.observers.push((id, Box::new(observer)));
+
+        id
+    }
+
+    /// Stop notifying an observer, returns whether it was added
+    pub fn remove_observer(&mut self, id: ObserverId) -> bool {
+        let len = self.observers.len();
+        self.observers.retain(|(observer, _)| *observer != id);
+
+        self.observers.len() != len
+    }
+
+    /// Read the frametime histogram of an attached application since its previous snapshot
+    ///
+    /// Only available in [`FrameMode::Histogram`], where the frametimes are binned by the ebpf program
+    /// instead of being sent one by one, so a periodic snapshot costs no wakeup per frame
+    ///
+    /// # Errors
+    ///
+    /// - `HistogramDisabled` if the analyzer is not built with [`FrameMode::Histogram`]
+    /// - `AppNotFound` if the target app is not attached
+    /// - `BpfMapError` if reading the histogram map fails
+    ///
+    /// # Examples
+    /// ```
+    /// use std::{thread, time::Duration};
+    /// # use frame_analyzer::{Analyzer, FrameMode};
+    ///
+    /// # fn main() {
+    /// #   let _ = try_main();
+    /// # }
+    /// #
+    /// # fn try_main() -> anyhow::Result<()> {
+    /// #   let app_pid = 2;
+    /// let mut analyzer = Analyzer::builder()
+    ///     .frame_mode(FrameMode::Histogram)
+    ///     .build()?;
+    /// analyzer.attach_app(app_pid)?;
+    ///
+    /// thread::sleep(Duration::from_secs(1));
+    /// let histogram = analyzer.snapshot_histogram(app_pid)?;
+    /// println!("frames: {}, p99: {:?}", histogram.count(), histogram.percentile(99.0));
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn snapshot_histogram(&mut self, pid: Pid) -> Result<Histogram> {
+        if self.frame_mode != FrameMode::Histogram {
+            return Err(AnalyzerError::HistogramDisabled);
+        }
 
-            let _ = self.register_poll();
+        if !self.contains(pid) {
+            return Err(AnalyzerError::AppNotFound);
         }
+        let handler = self
+            .sources
+            .uprobe_of(pid)
+            .ok_or(AnalyzerError::AppNotFound)?;
+
+        Ok(Histogram::from_bins(&handler.take_histogram(pid as u32)?))
+    }
 
-        let pid = self.buffer.pop_front()?;
-        let frametime = self.map.get_mut(&pid)?.update()?;
+    /// Number of frames of an attached application lost because the ebpf ring buffer was full, since it was attached
+    ///
+    /// Always `0` for the analyzers [fed by another source](Analyzer::with_source)
+    ///
+    /// # Errors
+    ///
+    /// - `AppNotFound` if the target app is not attached
+    /// - `BpfMapError` if reading the counter map fails
+    ///
+    /// # Examples
+    /// ```
+    /// # use frame_analyzer::Analyzer;
+    /// #
+    /// # fn main() {
+    /// #   let _ = try_main();
+    /// # }
+    /// #
+    /// # fn try_main() -> anyhow::Result<()> {
+    /// #   let app_pid = 2;
+    /// let mut analyzer = Analyzer::new()?;
+    /// analyzer.attach_app(app_pid)?;
+    /// // ...
+    /// println!("dropped frames: {}", analyzer.dropped_events(app_pid)?);
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn dropped_events(&mut self, pid: Pid) -> Result<u64> {
+        if !self.contains(pid) {
+            return Err(AnalyzerError::AppNotFound);
+        }
 
-        Some((pid, frametime))
+        self.sources
+            .uprobe_of(pid)
+            .map_or(Ok(0), |handler| handler.dropped(pid as u32))
+    }
+
+    /// Supply the display refresh period, frames are annotated with it from now on, see [`FrameEvent::vsyncs`]
+    ///
+    /// Replaces the provider set by [`Analyzer::set_refresh_rate_provider`], `None` stops annotating the frames
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    /// # use frame_analyzer::Analyzer;
+    ///
+    /// # fn main() {
+    /// #   let _ = try_main();
+    /// # }
+    /// #
+    /// # fn try_main() -> anyhow::Result<()> {
+    /// let mut analyzer = Analyzer::new()?;
+    /// analyzer.set_refresh_period(Some(Duration::from_secs(1) / 120));
+    /// #   let app_pid = 2;
+    /// analyzer.attach_app(app_pid)?;
+    ///
+    /// if let Some(event) = analyzer.recv_event() {
+    ///     println!("missed vsyncs: {:?}", event.missed_vsyncs());
+    /// }
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn set_refresh_period(&mut self, period: Option<Duration>) {
+        self.refresh.set_period(period);
+    }
+
+    /// Discover the display refresh period from a provider, queried once per `interval` on a background thread
+    ///
+    /// # Examples
+    /// ```
+    /// use std::time::Duration;
+    /// # use frame_analyzer::Analyzer;
+    /// use frame_analyzer::refresh::{DumpsysRefreshRate, SysfsRefreshRate};
+    ///
+    /// # fn main() {
+    /// #   let _ = try_main();
+    /// # }
+    /// #
+    /// # fn try_main() -> anyhow::Result<()> {
+    /// let mut analyzer = Analyzer::new()?;
+    /// if let Some(sysfs) = SysfsRefreshRate::discover() {
+    ///     analyzer.set_refresh_rate_provider(sysfs, Duration::from_millis(500));
+    /// } else {
+    ///     analyzer.set_refresh_rate_provider(DumpsysRefreshRate, Duration::from_secs(5));
+    /// }
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn set_refresh_rate_provider<P: RefreshRateProvider + 'static>(
+        &mut self,
+        provider: P,
+        interval: Duration,
+    ) {
+        self.refresh.set_provider(Box::new(provider), interval);
+    }
+
+    /// The current display refresh period, `None` if it is unknown
+    ///
+    /// A provider is queried on a background thread, so this is `None` until its first answer
+    #[must_use]
+    pub fn refresh_period(&self) -> Option<Duration> {
+        self.refresh.period()
+    }
+
+    /// Record every frame signal of the attached applications to a trace file from now on
+    ///
+    /// The signals are recorded before surface selection, so a replay goes through the same analysis.
+    /// Replaces the previous recorder, which is returned
+    ///
+    /// # Examples
+    /// ```
+    /// # use frame_analyzer::Analyzer;
+    /// use frame_analyzer::trace::{SessionMetadata, TraceWriter};
+    ///
+    /// # fn main() {
+    /// #   let _ = try_main();
+    /// # }
+    /// #
+    /// # fn try_main() -> anyhow::Result<()> {
+    /// #   let app_pid = 2;
+    /// let mut analyzer = Analyzer::new()?;
+    /// analyzer.attach_app(app_pid)?;
+    ///
+    /// let metadata = SessionMetadata {
+    ///     package: "com.example.game".into(),
+    ///     ..SessionMetadata::default()
+    /// };
+    /// analyzer.start_recording(TraceWriter::create("/data/local/tmp/session.fatrace", &metadata)?);
+    /// // ...
+    /// if let Some(recorder) = analyzer.stop_recording() {
+    ///     recorder.finish()?;
+    /// }
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub const fn start_recording(&mut self, recorder: TraceWriter) -> Option<TraceWriter> {
+        self.recorder.replace(recorder)
+    }
+
+    /// Stop recording, returns the recorder so that it can be [finished](TraceWriter::finish)
+    pub const fn stop_recording(&mut self) -> Option<TraceWriter> {
+        self.recorder.take()
     }
 
     /// Whether the target application has been attached by the `Analyzer`
@@ -357,24 +998,125 @@ impl Analyzer {
         self.map.keys().copied()
     }
 
-    fn register_poll(&mut self) -> Result<()> {
-        let poll = Poll::new()?;
+    /// Whether all frames have been received and no more will come, i.e. a [replay](Analyzer::with_replay) reached its end
+    ///
+    /// Always `false` for the ebpf uprobes
+    #[must_use]
+    pub fn is_exhausted(&self) -> bool {
+        self.pending.is_empty() && self.sources.is_exhausted()
+    }
+
+    /// A frame of the signals of one poll, `None` if none of them produced one, errors are ignored
+    fn poll_event(&mut self, timeout: Option<Duration>) -> Option<FrameEvent> {
+        self.error = None;
 
-        for (pid, handler) in &mut self.map {
-            poll.registry().register(
-                &mut SourceFd(&handler.uprobe.ring()?.as_raw_fd()),
-                Token(*pid as usize),
-                Interest::READABLE,
-            )?;
+        loop {
+            match self.next_signal(timeout) {
+                Ok(RecvOutcome::Frame(event)) => return Some(event),
+                Ok(RecvOutcome::Skipped { .. } | RecvOutcome::Detached(_)) | Err(_)
+                    if !self.pending.is_empty() => {}
+                _ => return None,
+            }
         }
+    }
 
-        self.poll = Some(poll);
-        Ok(())
+    /// The outcome of the next pending signal, polling the sources if there is none
+    fn next_signal(&mut self, timeout: Option<Duration>) -> Result<RecvOutcome> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        if self.pending.is_empty() {
+            if mem::take(&mut self.woken) {
+                return Ok(RecvOutcome::Interrupted);
+            }
+
+            if self.sources.is_exhausted() {
+                return Ok(RecvOutcome::Exhausted);
+            }
+
+            let mut events = Events::with_capacity(EVENT_MAX);
+            match self.poll.poll(&mut events, timeout) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    return Ok(RecvOutcome::Interrupted);
+                }
+                result => result?,
+            }
+
+            // every source is drained, the first error is returned once the signals are pending
+            let mut drained = Ok(());
+            for event in &events {
+                if event.token() == WAKE_TOKEN {
+                    self.woken = true;
+                } else if let Some(source) = self.sources.get_mut(event.token()) {
+                    let result = source.drain(&mut self.pending);
+                    if drained.is_ok() {
+                        drained = result;
+                    }
+                }
+            }
+
+            self.register_poll()?;
+            drained?;
+        }
+
+        Ok(match self.pending.pop_front() {
+            Some(signal) => self.update(&signal),
+            None if mem::take(&mut self.woken) => RecvOutcome::Interrupted,
+            None => RecvOutcome::Timeout,
+        })
     }
-}
 
-fn event_to_pid(event: &Event) -> Pid {
-    let token = event.token();
-    let Token(pid) = token;
-    pid as Pid
+    fn update(&mut self, signal: &FrameSignal) -> RecvOutcome {
+        let pid = signal.pid as Pid;
+        let Some(target) = self.map.get_mut(&pid) else {
+            return RecvOutcome::Detached(pid);
+        };
+
+        if let Some(ref mut recorder) = self.recorder
+            && let Err(e) = recorder.write(&TraceRecord::from(signal))
+        {
+            self.error = Some(e);
+        }
+
+        let selected = target.selected();
+        let frametime = target.update(signal);
+
+        if target.selected() != selected
+            && let Some(surface) = target.selected()
+        {
+            if self.frame_mode.suppress_unselected()
+                && let Some(handler) = self.sources.uprobe_of(pid)
+                && let Err(e) = handler.select_surface(signal.pid, surface)
+            {
+                self.error = Some(e);
+            }
+
+            for (_, observer) in &mut self.observers {
+                observer.on_surface_change(pid, surface);
+            }
+        }
+
+        let Some(frametime) = frametime else {
+            return RecvOutcome::Skipped {
+                pid,
+                surface: signal.buffer,
+            };
+        };
+        let event = FrameEvent::new(pid, signal.buffer, signal.ktime_ns, frametime);
+        let event = self
+            .refresh
+            .period()
+            .map_or(event, |period| event.with_refresh_period(period));
+
+        for (_, observer) in &mut self.observers {
+            observer.on_frame(&event);
+        }
+
+        RecvOutcome::Frame(event)
+    }
+
+    fn register_poll(&mut self) -> Result<()> {
+        self.sources.register(self.poll.registry())
+    }
 }
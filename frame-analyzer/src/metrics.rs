@@ -0,0 +1,417 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Expose the frame metrics over HTTP in the OpenMetrics text format, for Prometheus to scrape
+//!
+//! Feed a [`MetricsRegistry`] from the event stream of the [`Analyzer`] and serve it with a [`MetricsServer`].
+//! Every metric carries a `pid` label
+//!
+//! - `frame_analyzer_fps`: gauge, frames per second over the last second, `0` once the app stops queueing frames
+//! - `frame_analyzer_frames_total`: counter of frames
+//! - `frame_analyzer_frametime_seconds`: histogram of the frametimes, see [`BUCKETS`]
+//! - `frame_analyzer_janks_total`: counter of janky frames, with a `kind` label of `jank` or `big_jank`
+//! - `frame_analyzer_dropped_events_total`: counter of frames lost by the ebpf ring buffer
+//!
+//! # Examples
+//!
+//! ```
+//! use std::{
+//!     io::{Read, Write},
+//!     net::TcpStream,
+//!     time::Duration,
+//! };
+//!
+//! use frame_analyzer::{
+//!     FrameEvent,
+//!     metrics::{MetricsRegistry, MetricsServer},
+//! };
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let registry = MetricsRegistry::new();
+//! let server = MetricsServer::bind("127.0.0.1:0", registry.clone())?;
+//!
+//! for i in 1..=60 {
+//!     registry.record(&FrameEvent::new(42, 0x7f00, i * 16_666_667, Duration::from_nanos(16_666_667)));
+//! }
+//!
+//! let mut stream = TcpStream::connect(server.local_addr())?;
+//! stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+//! let mut response = String::new();
+//! stream.read_to_string(&mut response)?;
+//!
+//! assert!(response.starts_with("HTTP/1.1 200 OK"));
+//! assert!(response.contains("frame_analyzer_frames_total{pid=\"42\"} 60\n"));
+//! assert!(response.contains("frame_analyzer_frametime_seconds_bucket{pid=\"42\",le=\"+Inf\"} 60\n"));
+//! assert!(response.ends_with("# EOF\n"));
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    Analyzer, FrameEvent, Pid,
+    error::Result,
+    jank::{JankDetector, JankKind, JankRule},
+    stats::{FrameStats, Window},
+};
+
+/// Window of the fps gauge
+const FPS_WINDOW: Duration = Duration::from_secs(1);
+/// How long a scrape may take to send its request or read the response
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+/// Connections served at once, the excess is closed unanswered
+const MAX_CLIENTS: usize = 8;
+
+/// Upper bounds of the frametime histogram buckets: the frame intervals of common refresh rates,
+/// then two and three frames of a 24 fps movie (the PerfDog jank thresholds), then up to 1s
+pub const BUCKETS: [Duration; 12] = [
+    Duration::from_nanos(4_166_667),
+    Duration::from_nanos(8_333_333),
+    Duration::from_nanos(11_111_111),
+    Duration::from_nanos(16_666_667),
+    Duration::from_nanos(22_222_222),
+    Duration::from_nanos(33_333_333),
+    Duration::from_millis(50),
+    Duration::from_nanos(83_333_333),
+    Duration::from_millis(125),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+];
+
+struct AppMetrics {
+    fps: FrameStats,
+    last_frame: Option<Instant>,
+    jank: JankDetector,
+    buckets: [u64; BUCKETS.len()],
+    frames: u64,
+    sum: Duration,
+    dropped: u64,
+}
+
+impl AppMetrics {
+    fn new() -> Self {
+        Self {
+            fps: FrameStats::new(Window::Duration(FPS_WINDOW)),
+            last_frame: None,
+            jank: JankDetector::new(JankRule::PerfDog, Window::Frames(1)),
+            buckets: [0; BUCKETS.len()],
+            frames: 0,
+            sum: Duration::ZERO,
+            dropped: 0,
+        }
+    }
+}
+
+/// The metrics of every recorded app, cheap to clone and shared with a [`MetricsServer`]
+#[derive(Clone, Default)]
+pub struct MetricsRegistry {
+    apps: Arc<Mutex<BTreeMap<Pid, AppMetrics>>>,
+}
+
+impl MetricsRegistry {
+    /// Create an empty registry
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a frame
+    ///
+    /// Janks are classified by [`JankRule::vsync`] if the refresh period of the frame is known, by [`JankRule::PerfDog`] otherwise
+    pub fn record(&self, event: &FrameEvent) {
+        let mut apps = self.apps.lock().unwrap_or_else(PoisonError::into_inner);
+        let app = apps.entry(event.pid).or_insert_with(AppMetrics::new);
+
+        app.fps.push(event);
+        app.last_frame = Some(Instant::now());
+        app.jank.set_rule(
+            event
+                .refresh_period
+                .map_or(JankRule::PerfDog, JankRule::vsync),
+        );
+        app.jank.push(event);
+        app.frames += 1;
+        app.sum += event.frametime;
+        for (bucket, bound) in app.buckets.iter_mut().zip(BUCKETS) {
+            if event.frametime <= bound {
+                *bucket += 1;
+            }
+        }
+        drop(apps);
+    }
+
+    /// Set the number of frames of `pid` lost so far, e.g. from [`Analyzer::dropped_events`]
+    pub fn set_dropped(&self, pid: Pid, dropped: u64) {
+        self.apps
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(pid)
+            .or_insert_with(AppMetrics::new)
+            .dropped = dropped;
+    }
+
+    /// Update the dropped frames of every app attached to `analyzer`
+    ///
+    /// # Errors
+    ///
+    /// See [`Analyzer::dropped_events`]
+    pub fn sync_dropped(&self, analyzer: &mut Analyzer) -> Result<()> {
+        let pids: Vec<_> = analyzer.pids().collect();
+        for pid in pids {
+            self.set_dropped(pid, analyzer.dropped_events(pid)?);
+        }
+
+        Ok(())
+    }
+
+    /// Stop exposing the metrics of `pid`, e.g. after it is detached
+    pub fn remove(&self, pid: Pid) {
+        self.apps
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&pid);
+    }
+
+    /// Render the metrics in the OpenMetrics text format
+    #[must_use]
+    pub fn render(&self) -> String {
+        let apps = self.apps.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut text = String::new();
+
+        let _ = writeln!(
+            text,
+            "# TYPE frame_analyzer_fps gauge\n# HELP frame_analyzer_fps Frames per second over the last second."
+        );
+        for (pid, app) in apps.iter() {
+            // the window only moves with new frames, so it would keep the last fps of a stopped app forever
+            let fps = app
+                .last_frame
+                .filter(|last_frame| last_frame.elapsed() <= FPS_WINDOW)
+                .and_then(|_| app.fps.average_fps())
+                .unwrap_or_default();
+            let _ = writeln!(text, "frame_analyzer_fps{{pid=\"{pid}\"}} {fps}");
+        }
+
+        let _ = writeln!(
+            text,
+            "# TYPE frame_analyzer_frames counter\n# HELP frame_analyzer_frames Frames queued by the app."
+        );
+        for (pid, app) in apps.iter() {
+            let _ = writeln!(
+                text,
+                "frame_analyzer_frames_total{{pid=\"{pid}\"}} {}",
+                app.frames
+            );
+        }
+
+        let _ = writeln!(
+            text,
+            "# TYPE frame_analyzer_frametime_seconds histogram\n# HELP frame_analyzer_frametime_seconds Time between two frames of the app."
+        );
+        for (pid, app) in apps.iter() {
+            for (count, bound) in app.buckets.iter().zip(BUCKETS) {
+                let _ = writeln!(
+                    text,
+                    "frame_analyzer_frametime_seconds_bucket{{pid=\"{pid}\",le=\"{}\"}} {count}",
+                    bound.as_secs_f64()
+                );
+            }
+            let _ = writeln!(
+                text,
+                "frame_analyzer_frametime_seconds_bucket{{pid=\"{pid}\",le=\"+Inf\"}} {frames}\n\
+                 frame_analyzer_frametime_seconds_sum{{pid=\"{pid}\"}} {}\n\
+                 frame_analyzer_frametime_seconds_count{{pid=\"{pid}\"}} {frames}",
+                app.sum.as_secs_f64(),
+                frames = app.frames,
+            );
+        }
+
+        let _ = writeln!(
+            text,
+            "# TYPE frame_analyzer_janks counter\n# HELP frame_analyzer_janks Janky frames of the app."
+        );
+        for (pid, app) in apps.iter() {
+            let total = app.jank.total();
+            for (kind, count) in [
+                (JankKind::Jank, total.jank),
+                (JankKind::BigJank, total.big_jank),
+            ] {
+                let kind = if kind == JankKind::Jank {
+                    "jank"
+                } else {
+                    "big_jank"
+                };
+                let _ = writeln!(
+                    text,
+                    "frame_analyzer_janks_total{{pid=\"{pid}\",kind=\"{kind}\"}} {count}"
+                );
+            }
+        }
+
+        let _ = writeln!(
+            text,
+            "# TYPE frame_analyzer_dropped_events counter\n# HELP frame_analyzer_dropped_events Frames lost because the ebpf ring buffer was full."
+        );
+        for (pid, app) in apps.iter() {
+            let _ = writeln!(
+                text,
+                "frame_analyzer_dropped_events_total{{pid=\"{pid}\"}} {}",
+                app.dropped
+            );
+        }
+        drop(apps);
+
+        text.push_str("# EOF\n");
+        text
+    }
+}
+
+/// A minimal HTTP server exposing a [`MetricsRegistry`] at `/metrics`, on a background thread
+///
+/// Every connection is answered on its own thread, so an idle one can't hold the scrapes back.
+/// At most 8 are served at once, the excess is closed.
+/// The server stops when it is dropped
+pub struct MetricsServer {
+    addr: SocketAddr,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    /// Listen on `addr`, e.g. `0.0.0.0:9100`
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if binding fails
+    pub fn bind<A: ToSocketAddrs>(addr: A, registry: MetricsRegistry) -> Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let running = Arc::new(AtomicBool::new(true));
+        let clients = Arc::new(AtomicUsize::new(0));
+
+        let thread = {
+            let running = running.clone();
+            thread::Builder::new()
+                .name("metrics-server".into())
+                .spawn(move || {
+                    for stream in listener.incoming() {
+                        if !running.load(Ordering::Acquire) {
+                            break;
+                        }
+
+                        let Ok(stream) = stream else {
+                            continue;
+                        };
+                        if clients.fetch_add(1, Ordering::AcqRel) >= MAX_CLIENTS {
+                            clients.fetch_sub(1, Ordering::AcqRel);
+                            continue;
+                        }
+
+                        let registry = registry.clone();
+                        let clients_done = clients.clone();
+                        let spawned =
+                            thread::Builder::new()
+                                .name("metrics-client".into())
+                                .spawn(move || {
+                                    let _ = serve(&stream, &registry);
+                                    clients_done.fetch_sub(1, Ordering::AcqRel);
+                                });
+                        if spawned.is_err() {
+                            clients.fetch_sub(1, Ordering::AcqRel);
+                        }
+                    }
+                })?
+        };
+
+        Ok(Self {
+            addr,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    /// The address the server listens on
+    #[must_use]
+    pub const fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Release);
+        // wake up the blocking accept
+        let _ = TcpStream::connect(self.addr);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Answer a single request and close the connection
+fn serve(stream: &TcpStream, registry: &MetricsRegistry) -> Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // skip the headers
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "application/openmetrics-text; version=1.0.0; charset=utf-8",
+            registry.render(),
+        ),
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "Not Found\n".into()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method Not Allowed\n".into(),
+        ),
+    };
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()?;
+
+    Ok(())
+}
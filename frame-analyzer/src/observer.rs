@@ -0,0 +1,91 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use crate::{FrameEvent, Pid};
+
+/// Push-style notifications of an [`Analyzer`](crate::Analyzer), see [`Analyzer::add_observer`](crate::Analyzer::add_observer)
+///
+/// Observers see every frame the analyzer produces, whether it is driven by [`Analyzer::run`](crate::Analyzer::run)
+/// or by the `recv` methods. Closures taking a `&FrameEvent` are observers of the frames only
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::mpsc::{self, Sender};
+///
+/// use frame_analyzer::{Analyzer, FrameEvent, FrameObserver, FrameSignal, Pid, source::MemorySource};
+///
+/// struct Surfaces(Sender<(Pid, usize)>);
+///
+/// impl FrameObserver for Surfaces {
+///     fn on_frame(&mut self, _event: &FrameEvent) {}
+///
+///     fn on_surface_change(&mut self, pid: Pid, surface: usize) {
+///         let _ = self.0.send((pid, surface));
+///     }
+/// }
+///
+/// # fn main() -> anyhow::Result<()> {
+/// // the app draws to a surface, then to another one
+/// let first = (0..=10).map(|i| FrameSignal::new(i * 10_000_000, 0x7f00, 0, 42));
+/// let second = (0..=11).map(|i| FrameSignal::new(200_000_000 + i * 8_000_000, 0x8f00, 0, 42));
+/// let mut analyzer = Analyzer::with_source(MemorySource::from_signals(first.chain(second))?)?;
+/// analyzer.attach_app(42)?;
+///
+/// let (sender, surfaces) = mpsc::channel();
+/// analyzer.add_observer(Surfaces(sender));
+///
+/// let mut frames = 0;
+/// analyzer.run_with(|_| frames += 1)?; // until the source is exhausted
+/// assert_eq!(frames, 12);
+/// assert_eq!(surfaces.try_iter().collect::<Vec<_>>(), [(42, 0x7f00), (42, 0x8f00)]);
+/// # Ok(())
+/// # }
+/// ```
+pub trait FrameObserver: Send {
+    /// A frame of an attached app
+    fn on_frame(&mut self, event: &FrameEvent);
+
+    /// An attached app exited, it is already detached
+    ///
+    /// Only detected for the ebpf uprobes, while the analyzer is driven by [`Analyzer::run`](crate::Analyzer::run)
+    fn on_process_exit(&mut self, pid: Pid) {
+        let _ = pid;
+    }
+
+    /// The surface whose frames are reported for an app changed, including the first one
+    fn on_surface_change(&mut self, pid: Pid, surface: usize) {
+        let _ = (pid, surface);
+    }
+}
+
+impl<F: FnMut(&FrameEvent) + Send> FrameObserver for F {
+    fn on_frame(&mut self, event: &FrameEvent) {
+        self(event);
+    }
+}
+
+/// Identifies an observer added with [`Analyzer::add_observer`](crate::Analyzer::add_observer)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverId(u64);
+
+impl ObserverId {
+    pub(crate) const fn new(id: u64) -> Self {
+        Self(id)
+    }
+}
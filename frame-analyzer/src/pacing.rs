@@ -0,0 +1,298 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Frame pacing & microstutter metrics
+//!
+//! Two streams with the same average fps can feel very different, e.g. `33ms, 16ms, 33ms, 16ms...` against a steady 25ms.
+//! [`PacingAnalyzer`] tracks how uneven the frametimes are over a sliding window of frames
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use frame_analyzer::pacing::PacingAnalyzer;
+//!
+//! let mut steady = PacingAnalyzer::new();
+//! let mut uneven = PacingAnalyzer::new();
+//! for i in 0..240 {
+//!     steady.push_frametime(Duration::from_micros(25_000));
+//!     uneven.push_frametime(Duration::from_micros(if i % 2 == 0 { 33_333 } else { 16_667 }));
+//! }
+//!
+//! let steady = steady.metrics().unwrap();
+//! let uneven = uneven.metrics().unwrap();
+//! let diff = steady.mean_frametime.as_secs_f64() - uneven.mean_frametime.as_secs_f64();
+//! assert!(diff.abs() < 1e-6);
+//! assert!(!steady.microstutter);
+//! assert!(uneven.microstutter);
+//! assert!(uneven.mean_delta > Duration::from_millis(16));
+//! ```
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{FrameEvent, target_fps::TargetFpsEstimator};
+
+/// Default number of recent frames the metrics are computed over
+pub const DEFAULT_WINDOW: usize = 120;
+
+/// Upper bounds (exclusive) of the buckets of [`PacingMetrics::delta_buckets`], the last bucket is unbounded
+pub const DELTA_BUCKET_BOUNDS: [Duration; 5] = [
+    Duration::from_millis(1),
+    Duration::from_millis(2),
+    Duration::from_millis(4),
+    Duration::from_millis(8),
+    Duration::from_millis(16),
+];
+
+/// Pacing metrics of the frames in the window of a [`PacingAnalyzer`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PacingMetrics {
+    /// Number of frames in the window
+    pub frames: usize,
+    /// The mean frametime
+    pub mean_frametime: Duration,
+    /// Variance of the frametimes, in ms²
+    pub variance: f64,
+    /// Standard deviation of the frametimes
+    pub std_dev: Duration,
+    /// Mean absolute difference between consecutive frametimes
+    pub mean_delta: Duration,
+    /// 95th percentile of the absolute difference between consecutive frametimes
+    pub p95_delta: Duration,
+    /// Distribution of the absolute difference between consecutive frametimes, see [`DELTA_BUCKET_BOUNDS`]
+    pub delta_buckets: [usize; DELTA_BUCKET_BOUNDS.len() + 1],
+    /// The frame interval the pacing error is relative to, the inferred or supplied target
+    pub target_interval: Option<Duration>,
+    /// Mean of `|frametime - target interval| / target interval` over the frames with a known target
+    pub pacing_error: Option<f64>,
+    /// Whether the frame stream is flagged as microstuttering, see [`PacingAnalyzer::set_microstutter_threshold`]
+    pub microstutter: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    frametime: f64,
+    delta: Option<f64>,
+    error: Option<f64>,
+}
+
+/// Computes pacing metrics incrementally on one frame stream (e.g. one pid)
+///
+/// The target interval of the pacing error is inferred by a [`TargetFpsEstimator`] unless supplied by [`PacingAnalyzer::set_target`]
+#[derive(Debug, Clone)]
+pub struct PacingAnalyzer {
+    window: usize,
+    frames: VecDeque<Frame>,
+    sum: f64,
+    sum_squares: f64,
+    sum_delta: f64,
+    deltas: usize,
+    sum_error: f64,
+    errors: usize,
+    estimator: TargetFpsEstimator,
+    target: Option<Duration>,
+    microstutter_threshold: f64,
+}
+
+impl Default for PacingAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PacingAnalyzer {
+    /// An analyzer over the latest [`DEFAULT_WINDOW`] frames
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_window(DEFAULT_WINDOW)
+    }
+
+    /// An analyzer over the latest `window` frames
+    #[must_use]
+    pub fn with_window(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            frames: VecDeque::with_capacity(window),
+            sum: 0.0,
+            sum_squares: 0.0,
+            sum_delta: 0.0,
+            deltas: 0,
+            sum_error: 0.0,
+            errors: 0,
+            estimator: TargetFpsEstimator::new(),
+            target: None,
+            microstutter_threshold: 0.2,
+        }
+    }
+
+    /// Supply the target frame interval instead of inferring it, `None` to infer it again
+    pub const fn set_target(&mut self, target: Option<Duration>) {
+        self.target = target;
+    }
+
+    /// A window is flagged as microstuttering when its mean consecutive-frame delta exceeds this fraction
+    /// of its mean frametime, `0.2` by default
+    pub const fn set_microstutter_threshold(&mut self, threshold: f64) {
+        self.microstutter_threshold = threshold;
+    }
+
+    /// The target frame interval the pacing error is currently relative to
+    #[must_use]
+    pub fn target_interval(&self) -> Option<Duration> {
+        self.target.or_else(|| {
+            self.estimator
+                .estimate()
+                .map(|estimate| estimate.interval())
+        })
+    }
+
+    /// Feed a frame
+    pub fn push(&mut self, event: &FrameEvent) {
+        self.push_frametime(event.frametime);
+    }
+
+    /// Feed a raw frametime
+    pub fn push_frametime(&mut self, frametime: Duration) {
+        self.estimator.push_frametime(frametime);
+
+        let frametime = duration_to_ms(frametime);
+        let delta = self
+            .frames
+            .back()
+            .map(|last| (frametime - last.frametime).abs());
+        let error = self.target_interval().map(|target| {
+            let target = duration_to_ms(target);
+            (frametime - target).abs() / target
+        });
+
+        self.sum += frametime;
+        self.sum_squares += frametime * frametime;
+        if let Some(delta) = delta {
+            self.sum_delta += delta;
+            self.deltas += 1;
+        }
+        if let Some(error) = error {
+            self.sum_error += error;
+            self.errors += 1;
+        }
+        self.frames.push_back(Frame {
+            frametime,
+            delta,
+            error,
+        });
+
+        while self.frames.len() > self.window {
+            self.pop_oldest();
+        }
+    }
+
+    fn pop_oldest(&mut self) {
+        let Some(frame) = self.frames.pop_front() else {
+            return;
+        };
+
+        self.sum -= frame.frametime;
+        self.sum_squares -= frame.frametime * frame.frametime;
+        if let Some(delta) = frame.delta {
+            self.sum_delta -= delta;
+            self.deltas -= 1;
+        }
+        if let Some(error) = frame.error {
+            self.sum_error -= error;
+            self.errors -= 1;
+        }
+
+        // the delta of the new oldest frame refers to a frame out of the window now
+        if let Some(oldest) = self.frames.front_mut()
+            && let Some(delta) = oldest.delta.take()
+        {
+            self.sum_delta -= delta;
+            self.deltas -= 1;
+        }
+    }
+
+    /// Drop all frames
+    pub fn reset(&mut self) {
+        self.frames.clear();
+        self.sum = 0.0;
+        self.sum_squares = 0.0;
+        self.sum_delta = 0.0;
+        self.deltas = 0;
+        self.sum_error = 0.0;
+        self.errors = 0;
+        self.estimator.reset();
+    }
+
+    /// The metrics of the frames in the window, `None` if it is empty
+    #[must_use]
+    pub fn metrics(&self) -> Option<PacingMetrics> {
+        if self.frames.is_empty() {
+            return None;
+        }
+
+        let frames = self.frames.len() as f64;
+        let mean = self.sum / frames;
+        // clamp the rounding errors of the running sums
+        let variance = mean.mul_add(-mean, self.sum_squares / frames).max(0.0);
+        let mean_delta = if self.deltas == 0 {
+            0.0
+        } else {
+            (self.sum_delta / self.deltas as f64).max(0.0)
+        };
+
+        let mut deltas: Vec<_> = self.frames.iter().filter_map(|frame| frame.delta).collect();
+        deltas.sort_unstable_by(f64::total_cmp);
+        let p95_delta = if deltas.is_empty() {
+            0.0
+        } else {
+            let rank = (0.95 * deltas.len() as f64).ceil() as usize;
+            deltas[rank.clamp(1, deltas.len()) - 1]
+        };
+
+        let mut delta_buckets = [0; DELTA_BUCKET_BOUNDS.len() + 1];
+        for delta in &deltas {
+            let bucket = DELTA_BUCKET_BOUNDS
+                .iter()
+                .position(|bound| *delta < duration_to_ms(*bound))
+                .unwrap_or(DELTA_BUCKET_BOUNDS.len());
+            delta_buckets[bucket] += 1;
+        }
+
+        Some(PacingMetrics {
+            frames: self.frames.len(),
+            mean_frametime: ms_to_duration(mean),
+            variance,
+            std_dev: ms_to_duration(variance.sqrt()),
+            mean_delta: ms_to_duration(mean_delta),
+            p95_delta: ms_to_duration(p95_delta),
+            delta_buckets,
+            target_interval: self.target_interval(),
+            pacing_error: (self.errors > 0).then(|| (self.sum_error / self.errors as f64).max(0.0)),
+            microstutter: mean > 0.0 && mean_delta / mean > self.microstutter_threshold,
+        })
+    }
+}
+
+fn duration_to_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn ms_to_duration(ms: f64) -> Duration {
+    Duration::from_secs_f64(ms.max(0.0) / 1000.0)
+}
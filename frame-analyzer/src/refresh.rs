@@ -0,0 +1,246 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Display refresh rate awareness
+//!
+//! Frametimes are easier to reason about in vsync intervals of the display, the [`Analyzer`](crate::Analyzer) annotates
+//! every [`FrameEvent`](crate::FrameEvent) with the refresh period and the number of vsync intervals it spanned
+//! once it knows the refresh period, either supplied directly or discovered by a [`RefreshRateProvider`]
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use frame_analyzer::refresh::{self, FixedRefreshRate, RefreshRateProvider};
+//!
+//! let mut provider = FixedRefreshRate::from_hz(120.0);
+//! let period = provider.refresh_period().unwrap();
+//!
+//! assert_eq!(refresh::vsyncs(Duration::from_micros(8_333), period), 1);
+//! assert_eq!(refresh::vsyncs(Duration::from_micros(25_000), period), 3);
+//! ```
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::Duration,
+};
+
+/// Sysfs nodes exposing the refresh rate on common devices, tried in order by [`SysfsRefreshRate::discover`]
+pub const SYSFS_CANDIDATES: &[&str] = &[
+    "/sys/class/drm/sde-crtc-0/measured_fps",
+    "/sys/class/graphics/fb0/measured_fps",
+    "/sys/class/graphics/fb0/dynamic_fps",
+];
+
+/// A source of the current display refresh period
+pub trait RefreshRateProvider: Send {
+    /// The current refresh period, `None` if it is unknown
+    fn refresh_period(&mut self) -> Option<Duration>;
+}
+
+/// A refresh rate supplied by the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedRefreshRate {
+    period: Duration,
+}
+
+impl FixedRefreshRate {
+    /// A fixed refresh period
+    #[must_use]
+    pub const fn new(period: Duration) -> Self {
+        Self { period }
+    }
+
+    /// A fixed refresh rate in Hz
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hz` is not positive and finite
+    #[must_use]
+    pub fn from_hz(hz: f64) -> Self {
+        Self::new(Duration::from_secs_f64(1.0 / hz))
+    }
+}
+
+impl RefreshRateProvider for FixedRefreshRate {
+    fn refresh_period(&mut self) -> Option<Duration> {
+        Some(self.period)
+    }
+}
+
+/// Read the refresh rate in Hz from a sysfs node, the first number in the node is used
+///
+/// The nodes are vendor specific, see [`SYSFS_CANDIDATES`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SysfsRefreshRate {
+    path: PathBuf,
+}
+
+impl SysfsRefreshRate {
+    /// Read the refresh rate from `path`
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The first readable node of [`SYSFS_CANDIDATES`]
+    #[must_use]
+    pub fn discover() -> Option<Self> {
+        SYSFS_CANDIDATES
+            .iter()
+            .map(Self::new)
+            .find(|provider| provider.read_hz().is_some())
+    }
+
+    fn read_hz(&self) -> Option<f64> {
+        let content = fs::read_to_string(&self.path).ok()?;
+        first_number(&content)
+    }
+}
+
+impl RefreshRateProvider for SysfsRefreshRate {
+    fn refresh_period(&mut self) -> Option<Duration> {
+        self.read_hz().and_then(hz_to_period)
+    }
+}
+
+/// Ask SurfaceFlinger through `dumpsys SurfaceFlinger`, slow but available on every Android device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DumpsysRefreshRate;
+
+impl RefreshRateProvider for DumpsysRefreshRate {
+    fn refresh_period(&mut self) -> Option<Duration> {
+        let output = Command::new("dumpsys")
+            .arg("SurfaceFlinger")
+            .output()
+            .ok()?;
+        let output = String::from_utf8_lossy(&output.stdout);
+        parse_dumpsys(&output).and_then(hz_to_period)
+    }
+}
+
+/// Find the refresh rate in Hz in the output of `dumpsys SurfaceFlinger` or `dumpsys display`
+///
+/// # Examples
+///
+/// ```
+/// use frame_analyzer::refresh::parse_dumpsys;
+///
+/// assert_eq!(parse_dumpsys("refresh-rate              : 120.000000 fps"), Some(120.0));
+/// assert_eq!(parse_dumpsys("mDefaultModeId=1, mRefreshRate=90.0, mUserPreferredMode"), Some(90.0));
+/// assert_eq!(parse_dumpsys("nothing here"), None);
+/// ```
+#[must_use]
+pub fn parse_dumpsys(output: &str) -> Option<f64> {
+    ["refresh-rate", "mRefreshRate="].iter().find_map(|key| {
+        let start = output.find(key)? + key.len();
+        first_number(&output[start..])
+    })
+}
+
+/// Number of vsync intervals a frametime spanned, rounded to the nearest one and at least one
+#[must_use]
+pub fn vsyncs(frametime: Duration, period: Duration) -> u32 {
+    if period.is_zero() {
+        return 1;
+    }
+
+    (frametime.as_secs_f64() / period.as_secs_f64())
+        .round()
+        .max(1.0) as u32
+}
+
+fn hz_to_period(hz: f64) -> Option<Duration> {
+    (hz.is_finite() && hz > 0.0).then(|| Duration::from_secs_f64(1.0 / hz))
+}
+
+fn first_number(text: &str) -> Option<f64> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    let text = &text[start..];
+    let end = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    text[..end].parse().ok()
+}
+
+/// Caches the refresh period of a provider
+///
+/// A provider can be slow (`dumpsys` takes tens of milliseconds), so it is queried once per interval
+/// on a background thread and reading the period never blocks
+#[derive(Debug)]
+pub(crate) struct RefreshTracker {
+    /// The period in nanoseconds, `0` if it is unknown
+    period: Arc<AtomicU64>,
+    /// Stops the thread querying the provider once dropped
+    stop: Option<Sender<()>>,
+}
+
+impl RefreshTracker {
+    pub fn new() -> Self {
+        Self {
+            period: Arc::new(AtomicU64::new(0)),
+            stop: None,
+        }
+    }
+
+    pub fn set_period(&mut self, period: Option<Duration>) {
+        // a fresh cell, so a query of the old provider still running can't overwrite it
+        self.period = Arc::new(AtomicU64::new(period.map_or(0, period_to_nanos)));
+        self.stop = None;
+    }
+
+    pub fn set_provider(&mut self, mut provider: Box<dyn RefreshRateProvider>, interval: Duration) {
+        self.set_period(None);
+
+        let (stop, stopped) = mpsc::channel();
+        let period = self.period.clone();
+        let _ = thread::Builder::new()
+            .name("refresh-rate".into())
+            .spawn(move || {
+                loop {
+                    let nanos = provider.refresh_period().map_or(0, period_to_nanos);
+                    period.store(nanos, Ordering::Release);
+
+                    if stopped.recv_timeout(interval) != Err(RecvTimeoutError::Timeout) {
+                        return;
+                    }
+                }
+            });
+        self.stop = Some(stop);
+    }
+
+    pub fn period(&self) -> Option<Duration> {
+        match self.period.load(Ordering::Acquire) {
+            0 => None,
+            nanos => Some(Duration::from_nanos(nanos)),
+        }
+    }
+}
+
+fn period_to_nanos(period: Duration) -> u64 {
+    u64::try_from(period.as_nanos()).unwrap_or(u64::MAX).max(1)
+}
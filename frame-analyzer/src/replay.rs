@@ -0,0 +1,375 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Replay a recorded trace through the [`Analyzer`](crate::Analyzer) API
+//!
+//! A replay needs neither root nor an android device, so the analysis (surface selection, stats, jank detection...)
+//! can be tested deterministically on any linux machine, or a reported issue debugged offline
+//!
+//! # Examples
+//!
+//! ```
+//! use frame_analyzer::{
+//!     Analyzer,
+//!     replay::{ReplaySource, ReplaySpeed},
+//!     stats::{FrameStats, Window},
+//!     trace::{SessionMetadata, TraceRecord, TraceWriter},
+//! };
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let path = std::env::temp_dir().join("frame-analyzer-replay-doc.fatrace");
+//! let metadata = SessionMetadata {
+//!     refresh_rate: Some(60.0),
+//!     ..SessionMetadata::default()
+//! };
+//!
+//! let mut writer = TraceWriter::create(&path, &metadata)?;
+//! for i in 0..601 {
+//!     writer.write(&TraceRecord::new(i * 16_666_667, 42, 0x7f00, 0))?;
+//! }
+//! writer.finish()?;
+//!
+//! let mut analyzer = Analyzer::with_replay(ReplaySource::open(&path, ReplaySpeed::Max)?)?;
+//! analyzer.attach_app(42)?;
+//!
+//! let mut stats = FrameStats::new(Window::Frames(1000));
+//! while !analyzer.is_exhausted() {
+//!     if let Some(event) = analyzer.recv_event() {
+//!         stats.push(&event);
+//!     }
+//! }
+//!
+//! // the first frame of a surface has no frametime
+//! assert_eq!(stats.len(), 600);
+//! assert!((stats.average_fps().unwrap() - 60.0).abs() < 0.01);
+//! # std::fs::remove_file(&path)?;
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{self, BufReader, Read},
+    os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    path::Path,
+    ptr,
+    time::{Duration, Instant},
+};
+
+use frame_analyzer_ebpf_common::FrameSignal;
+
+use crate::{
+    FrameEvent, Pid,
+    analyze_target::AnalyzeTarget,
+    error::{AnalyzerError, Result},
+    source::FrameSource,
+    trace::{SessionMetadata, TraceReader, TraceRecord},
+};
+
+/// Signals handed to the analyzer per wakeup in [`ReplaySpeed::Max`]
+const MAX_BATCH: usize = 1024;
+/// Longest wait for a single record, so a very slow factor can't overflow the timer
+const MAX_DUE: Duration = Duration::from_secs(u32::MAX as u64);
+
+/// How fast a trace is replayed
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ReplaySpeed {
+    /// Keep the original timing of the frames
+    #[default]
+    RealTime,
+    /// Replay as fast as the analyzer consumes the frames
+    Max,
+    /// Replay the original timing accelerated by a factor, e.g. `2.0` is twice as fast
+    ///
+    /// The factor must be positive & finite, [`ReplaySource::new`] rejects anything else
+    Scaled(f64),
+}
+
+impl ReplaySpeed {
+    /// Whether a replay source accepts this speed
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use frame_analyzer::replay::ReplaySpeed;
+    ///
+    /// assert!(ReplaySpeed::Scaled(0.5).is_valid());
+    /// assert!(!ReplaySpeed::Scaled(0.0).is_valid());
+    /// assert!(!ReplaySpeed::Scaled(f64::NAN).is_valid());
+    /// ```
+    #[must_use]
+    pub const fn is_valid(&self) -> bool {
+        match *self {
+            Self::RealTime | Self::Max => true,
+            Self::Scaled(factor) => factor.is_finite() && factor > 0.0,
+        }
+    }
+}
+
+/// A frame source replaying a trace recorded by a [`TraceWriter`](crate::trace::TraceWriter)
+///
+/// Pass it to [`Analyzer::with_replay`](crate::Analyzer::with_replay), signals of the apps that are not attached are ignored
+pub struct ReplaySource {
+    reader: TraceReader<Box<dyn Read + Send>>,
+    timer: OwnedFd,
+    speed: ReplaySpeed,
+    clock: Option<(u64, Instant)>,
+    next: Option<TraceRecord>,
+    exhausted: bool,
+}
+
+impl ReplaySource {
+    /// Open the trace file at `path`
+    ///
+    /// # Errors
+    ///
+    /// - `IOError` if the file cannot be opened or read, or the timer cannot be created
+    /// - `InvalidTrace` if the file is not a trace file
+    /// - `InvalidReplaySpeed` if `speed` is not [valid](ReplaySpeed::is_valid)
+    pub fn open<P: AsRef<Path>>(path: P, speed: ReplaySpeed) -> Result<Self> {
+        let file = File::open(path)?;
+        Self::new(BufReader::new(file), speed)
+    }
+
+    /// Replay a trace from any reader
+    ///
+    /// # Errors
+    ///
+    /// - `IOError` if reading fails, or the timer cannot be created
+    /// - `InvalidTrace` if the header is invalid
+    /// - `InvalidReplaySpeed` if `speed` is not [valid](ReplaySpeed::is_valid)
+    pub fn new<R: Read + Send + 'static>(reader: R, speed: ReplaySpeed) -> Result<Self> {
+        if !speed.is_valid() {
+            return Err(AnalyzerError::InvalidReplaySpeed);
+        }
+        let reader = TraceReader::new(Box::new(reader) as Box<dyn Read + Send>)?;
+
+        let fd = unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let timer = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let source = Self {
+            reader,
+            timer,
+            speed,
+            clock: None,
+            next: None,
+            exhausted: false,
+        };
+        source.arm(Some(Duration::ZERO))?;
+
+        Ok(source)
+    }
+
+    /// The metadata of the replayed session
+    #[must_use]
+    pub const fn metadata(&self) -> &SessionMetadata {
+        self.reader.metadata()
+    }
+
+    /// Time until `record` is due, `Duration::ZERO` if it already is
+    fn due_in(&mut self, record: &TraceRecord) -> Duration {
+        let factor = match self.speed {
+            ReplaySpeed::Max => return Duration::ZERO,
+            ReplaySpeed::RealTime => 1.0,
+            ReplaySpeed::Scaled(factor) => factor,
+        };
+
+        let (first, start) = *self
+            .clock
+            .get_or_insert_with(|| (record.timestamp_ns, Instant::now()));
+        let offset = record.timestamp_ns.saturating_sub(first) as f64 / factor;
+        let offset = Duration::try_from_secs_f64(offset / 1_000_000_000.0).unwrap_or(MAX_DUE);
+
+        start
+            .checked_add(offset)
+            .map_or(MAX_DUE, |due| due.saturating_duration_since(Instant::now()))
+            .min(MAX_DUE)
+    }
+
+    /// Arm the timer to expire after `after`, disarm it with `None`
+    fn arm(&self, after: Option<Duration>) -> Result<()> {
+        // a zeroed it_value disarms the timer, so expire after 1ns at least
+        let value = after.map_or(
+            libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            |after| libc::timespec {
+                tv_sec: after.as_secs() as libc::time_t,
+                tv_nsec: libc::c_long::from(
+                    after.subsec_nanos().max(u32::from(after.as_secs() == 0)),
+                ),
+            },
+        );
+        let spec = libc::itimerspec {
+            it_interval: libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: value,
+        };
+
+        if unsafe {
+            libc::timerfd_settime(self.timer.as_raw_fd(), 0, &raw const spec, ptr::null_mut())
+        } < 0
+        {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(())
+    }
+}
+
+impl FrameSource for ReplaySource {
+    fn raw_fd(&mut self) -> Result<RawFd> {
+        Ok(self.timer.as_raw_fd())
+    }
+
+    fn drain(&mut self, signals: &mut VecDeque<FrameSignal>) -> Result<()> {
+        let mut expirations = [0u8; 8];
+        let _ = unsafe {
+            libc::read(
+                self.timer.as_raw_fd(),
+                expirations.as_mut_ptr().cast(),
+                expirations.len(),
+            )
+        };
+
+        let mut batch = 0;
+        loop {
+            let record = match self.next.take() {
+                Some(record) => record,
+                None => match self.reader.next() {
+                    Some(Ok(record)) => record,
+                    Some(Err(e)) => {
+                        self.exhausted = true;
+                        self.arm(None)?;
+                        return Err(e);
+                    }
+                    None => {
+                        self.exhausted = true;
+                        return self.arm(None);
+                    }
+                },
+            };
+
+            let due_in = self.due_in(&record);
+            if !due_in.is_zero() || batch == MAX_BATCH {
+                self.next = Some(record);
+                return self.arm(Some(due_in));
+            }
+
+            signals.push_back(FrameSignal::from(&record));
+            batch += 1;
+        }
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.exhausted
+    }
+}
+
+/// Analyze every app of a trace at once, as fast as it is read and without an [`Analyzer`](crate::Analyzer)
+///
+/// The frames go through the same surface selection, and are annotated with the refresh rate of the trace metadata if it was recorded
+///
+/// # Examples
+///
+/// ```
+/// use frame_analyzer::{
+///     replay,
+///     trace::{SessionMetadata, TraceReader, TraceRecord, TraceWriter},
+/// };
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let path = std::env::temp_dir().join("frame-analyzer-events-doc.fatrace");
+/// let mut writer = TraceWriter::create(&path, &SessionMetadata::default())?;
+/// for i in 0..=100 {
+///     writer.write(&TraceRecord::new(i * 10_000_000, 42, 0x7f00, 0))?;
+///     writer.write(&TraceRecord::new(i * 20_000_000, 43, 0x7f00, 0))?;
+/// }
+/// writer.finish()?;
+///
+/// let events = replay::events(TraceReader::open(&path)?).collect::<Result<Vec<_>, _>>()?;
+/// assert_eq!(events.iter().filter(|event| event.pid == 42).count(), 100);
+/// assert_eq!(events.iter().filter(|event| event.pid == 43).count(), 100);
+/// # std::fs::remove_file(&path)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn events<R: Read>(reader: TraceReader<R>) -> ReplayEvents<R> {
+    let period = reader
+        .metadata()
+        .refresh_rate
+        .filter(|hz| *hz > 0.0)
+        .map(|hz| Duration::from_secs_f64(1.0 / hz));
+
+    ReplayEvents {
+        reader,
+        targets: HashMap::new(),
+        period,
+    }
+}
+
+/// Iterator returned by [`events`]
+pub struct ReplayEvents<R: Read> {
+    reader: TraceReader<R>,
+    targets: HashMap<Pid, AnalyzeTarget>,
+    period: Option<Duration>,
+}
+
+impl<R: Read> ReplayEvents<R> {
+    /// The metadata of the replayed session
+    #[must_use]
+    pub const fn metadata(&self) -> &SessionMetadata {
+        self.reader.metadata()
+    }
+}
+
+impl<R: Read> Iterator for ReplayEvents<R> {
+    type Item = Result<FrameEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let record = match self.reader.next()? {
+                Ok(record) => record,
+                Err(e) => return Some(Err(e)),
+            };
+
+            let target = self
+                .targets
+                .entry(record.pid)
+                .or_insert_with(AnalyzeTarget::new);
+            if let Some(frametime) = target.update(&FrameSignal::from(&record)) {
+                let event =
+                    FrameEvent::new(record.pid, record.surface, record.timestamp_ns, frametime);
+                return Some(Ok(self
+                    .period
+                    .map_or(event, |period| event.with_refresh_period(period))));
+            }
+        }
+    }
+}
@@ -0,0 +1,397 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Summarize a recorded session into a report
+//!
+//! A [`Report`] holds, per app, the duration, fps, percentile frametimes, janks, the longest hitch,
+//! the fps over time and the time spent below a target fps.
+//! It renders to [Markdown](Report::markdown) and to a self-contained [HTML](Report::html) page with an fps chart
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use frame_analyzer::{
+//!     report::Report,
+//!     trace::{SessionMetadata, TraceReader, TraceRecord, TraceWriter},
+//! };
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let path = std::env::temp_dir().join("frame-analyzer-report-doc.fatrace");
+//! let metadata = SessionMetadata {
+//!     package: "com.example.game".into(),
+//!     refresh_rate: Some(60.0),
+//!     ..SessionMetadata::default()
+//! };
+//!
+//! // ten seconds at 60 fps, but one 250ms hitch
+//! let mut writer = TraceWriter::create(&path, &metadata)?;
+//! let mut timestamp = 0;
+//! for i in 0..600 {
+//!     timestamp += if i == 300 { 250_000_000 } else { 16_666_667 };
+//!     writer.write(&TraceRecord::new(timestamp, 42, 0x7f00, 0))?;
+//! }
+//! writer.finish()?;
+//!
+//! let report = Report::builder()
+//!     .target_fps(55.0)
+//!     .build(TraceReader::open(&path)?)?;
+//! let app = &report.apps[0];
+//! assert_eq!(app.package.as_deref(), Some("com.example.game"));
+//! assert_eq!(app.longest_hitch.unwrap().frametime, Duration::from_millis(250));
+//! // only the second of the hitch averages less than 55 fps
+//! assert!(app.time_below_target > Duration::from_millis(900));
+//! assert!(app.time_below_target < Duration::from_millis(1100));
+//!
+//! assert!(report.markdown().contains("| 42 | com.example.game |"));
+//! assert!(report.html().contains("<svg"));
+//! # std::fs::remove_file(&path)?;
+//! # Ok(())
+//! # }
+//! ```
+mod html;
+mod markdown;
+
+use std::{collections::BTreeMap, io::Read, time::Duration};
+
+use crate::{
+    FrameEvent, Pid,
+    error::Result,
+    jank::{JankCounts, JankDetector, JankRule},
+    replay,
+    stats::{FrameStats, StatsSummary, Window},
+    target_fps::TargetFpsEstimator,
+    trace::{SessionMetadata, TraceReader},
+};
+
+/// Default target of [`AppReport::time_below_target`] if the session has no refresh rate
+pub const DEFAULT_TARGET_FPS: f64 = 60.0;
+
+/// The report of a session
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Report {
+    /// The metadata of the session
+    pub metadata: SessionMetadata,
+    /// The fps an app is expected to reach
+    pub target_fps: f64,
+    /// Width of the samples of [`AppReport::fps_over_time`]
+    pub interval: Duration,
+    /// The apps of the session, by pid
+    pub apps: Vec<AppReport>,
+}
+
+/// The report of one app
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AppReport {
+    /// The pid of the application
+    pub pid: Pid,
+    /// The package name of the application, if it was recorded
+    pub package: Option<String>,
+    /// Time from the first to the last frame
+    pub duration: Duration,
+    /// Statistics over all the frames
+    pub summary: StatsSummary,
+    /// Janks over all the frames, see [`JankRule::capped`]
+    pub janks: JankCounts,
+    /// The longest frame
+    pub longest_hitch: Option<Hitch>,
+    /// Average fps of every interval with frames
+    pub fps_over_time: Vec<FpsSample>,
+    /// Time of the intervals averaging less than the target fps
+    pub time_below_target: Duration,
+}
+
+/// A long frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hitch {
+    /// When the frame was queued, in nanoseconds of `CLOCK_MONOTONIC`
+    pub timestamp_ns: u64,
+    /// When the frame was queued, since the first frame of the session
+    pub offset: Duration,
+    /// The frametime
+    pub frametime: Duration,
+}
+
+/// The average fps of an interval
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FpsSample {
+    /// Start of the interval, since the first frame of the session
+    pub offset: Duration,
+    /// Frames per second of the frames queued in the interval
+    pub fps: f64,
+}
+
+impl Report {
+    /// Configure a report
+    #[must_use]
+    pub const fn builder() -> ReportBuilder {
+        ReportBuilder::new()
+    }
+
+    /// The report as a Markdown document
+    #[must_use]
+    pub fn markdown(&self) -> String {
+        markdown::render(self)
+    }
+
+    /// The report as a self-contained HTML page, the fps charts are inline svg
+    #[must_use]
+    pub fn html(&self) -> String {
+        html::render(self)
+    }
+}
+
+/// Builder of a [`Report`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReportBuilder {
+    target_fps: Option<f64>,
+    interval: Duration,
+}
+
+impl Default for ReportBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReportBuilder {
+    /// Target fps of the session refresh rate, samples of one second
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            target_fps: None,
+            interval: Duration::from_secs(1),
+        }
+    }
+
+    /// The fps an app is expected to reach, the refresh rate of the session by default, or [`DEFAULT_TARGET_FPS`]
+    #[must_use]
+    pub const fn target_fps(mut self, target_fps: f64) -> Self {
+        self.target_fps = Some(target_fps);
+        self
+    }
+
+    /// Width of the fps samples, the time below the target fps is counted in whole intervals
+    #[must_use]
+    pub const fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Replay a recorded session into a report
+    ///
+    /// # Errors
+    ///
+    /// `InvalidTrace` or `IOError` if the trace cannot be read
+    pub fn build<R: Read>(self, reader: TraceReader<R>) -> Result<Report> {
+        let events = replay::events(reader);
+        let metadata = events.metadata().clone();
+        let target_fps = self
+            .target_fps
+            .or(metadata.refresh_rate)
+            .unwrap_or(DEFAULT_TARGET_FPS);
+        let interval = self.interval.max(Duration::from_millis(1));
+
+        let mut start = None;
+        let mut apps = BTreeMap::new();
+        for event in events {
+            let event = event?;
+            let start = *start.get_or_insert(event.timestamp_ns);
+            apps.entry(event.pid)
+                .or_insert_with(AppAccumulator::new)
+                .push(&event, start, interval);
+        }
+
+        let apps = apps
+            .into_iter()
+            .filter_map(|(pid, app)| {
+                let package = metadata.package_name(pid).map(str::to_string);
+                app.finish(pid, package, target_fps, interval)
+            })
+            .collect();
+
+        Ok(Report {
+            metadata,
+            target_fps,
+            interval,
+            apps,
+        })
+    }
+}
+
+/// The frames of an interval
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    frames: usize,
+    duration: Duration,
+}
+
+struct AppAccumulator {
+    first_ns: u64,
+    last_ns: u64,
+    stats: FrameStats,
+    jank: JankDetector,
+    target: TargetFpsEstimator,
+    longest_hitch: Option<Hitch>,
+    buckets: BTreeMap<u64, Bucket>,
+}
+
+impl AppAccumulator {
+    fn new() -> Self {
+        Self {
+            first_ns: u64::MAX,
+            last_ns: 0,
+            stats: FrameStats::new(Window::Duration(Duration::MAX)),
+            jank: JankDetector::new(JankRule::PerfDog, Window::Frames(1)),
+            target: TargetFpsEstimator::new(),
+            longest_hitch: None,
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    fn push(&mut self, event: &FrameEvent, start: u64, interval: Duration) {
+        self.first_ns = self.first_ns.min(event.timestamp_ns);
+        self.last_ns = self.last_ns.max(event.timestamp_ns);
+        self.stats.push(event);
+
+        self.target.push(event);
+        self.jank.set_rule(JankRule::capped(
+            event.refresh_period,
+            self.target.estimate(),
+        ));
+        self.jank.push(event);
+
+        let offset = Duration::from_nanos(event.timestamp_ns.saturating_sub(start));
+        if self
+            .longest_hitch
+            .is_none_or(|hitch| event.frametime > hitch.frametime)
+        {
+            self.longest_hitch = Some(Hitch {
+                timestamp_ns: event.timestamp_ns,
+                offset,
+                frametime: event.frametime,
+            });
+        }
+
+        let bucket = self
+            .buckets
+            .entry((offset.as_nanos() / interval.as_nanos()) as u64)
+            .or_default();
+        bucket.frames += 1;
+        bucket.duration += event.frametime;
+    }
+
+    fn finish(
+        self,
+        pid: Pid,
+        package: Option<String>,
+        target_fps: f64,
+        interval: Duration,
+    ) -> Option<AppReport> {
+        let summary = self.stats.summary()?;
+
+        let mut time_below_target = Duration::ZERO;
+        let fps_over_time = self
+            .buckets
+            .iter()
+            .filter(|(_, bucket)| !bucket.duration.is_zero())
+            .map(|(&index, bucket)| {
+                let fps = bucket.frames as f64 / bucket.duration.as_secs_f64();
+                if fps < target_fps {
+                    time_below_target += bucket.duration;
+                }
+                FpsSample {
+                    offset: Duration::from_nanos(index * interval.as_nanos() as u64),
+                    fps,
+                }
+            })
+            .collect();
+
+        Some(AppReport {
+            pid,
+            package,
+            duration: Duration::from_nanos(self.last_ns - self.first_ns),
+            summary,
+            janks: self.jank.total(),
+            longest_hitch: self.longest_hitch,
+            fps_over_time,
+            time_below_target,
+        })
+    }
+}
+
+/// The title of a report, the package of the session
+fn title(report: &Report) -> &str {
+    if report.metadata.package.is_empty() {
+        "session"
+    } else {
+        &report.metadata.package
+    }
+}
+
+fn format_ms(duration: Duration) -> String {
+    format!("{:.2} ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn format_secs(duration: Duration) -> String {
+    format!("{:.2} s", duration.as_secs_f64())
+}
+
+/// Percentage of the time of an app below the target fps
+fn below_target_percent(app: &AppReport) -> f64 {
+    if app.duration.is_zero() {
+        0.0
+    } else {
+        (app.time_below_target.as_secs_f64() / app.duration.as_secs_f64() * 100.0).min(100.0)
+    }
+}
+
+/// A unix time in milliseconds as an UTC date & time
+fn format_unix_ms(unix_ms: u64) -> String {
+    let secs = unix_ms / 1000;
+    let days = (secs / 86_400) as i64;
+    let time = secs % 86_400;
+
+    // days to civil date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
@@ -0,0 +1,206 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{fmt::Write, time::Duration};
+
+use super::{
+    AppReport, Report, below_target_percent, format_ms, format_secs, format_unix_ms, title,
+};
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin:1em 0}\
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:right}\
+th{background:#f4f4f4}td.text{text-align:left}\
+svg{background:#fafafa;border:1px solid #ddd}\
+.fps{fill:none;stroke:#1a73e8;stroke-width:1.5}\
+.target{stroke:#d93025;stroke-dasharray:4 4}\
+.axis{stroke:#888}text{font-size:11px;fill:#555}";
+
+const CHART_WIDTH: f64 = 760.0;
+const CHART_HEIGHT: f64 = 220.0;
+/// Room for the axis labels
+const CHART_MARGIN: f64 = 40.0;
+
+pub fn render(report: &Report) -> String {
+    let mut out = String::new();
+    let metadata = &report.metadata;
+    let title = escape(&format!("Frame report: {}", title(report)));
+
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n<ul>\n"
+    );
+    if !metadata.device.is_empty() {
+        let _ = writeln!(out, "<li>Device: {}</li>", escape(&metadata.device));
+    }
+    if metadata.start_unix_ms != 0 {
+        let _ = writeln!(
+            out,
+            "<li>Started: {}</li>",
+            format_unix_ms(metadata.start_unix_ms)
+        );
+    }
+    if let Some(refresh_rate) = metadata.refresh_rate {
+        let _ = writeln!(out, "<li>Refresh rate: {refresh_rate:.2} Hz</li>");
+    }
+    let _ = writeln!(out, "<li>Target: {:.1} fps</li>\n</ul>", report.target_fps);
+
+    out.push_str("<h2>Summary</h2>\n<table>\n<tr><th>PID</th><th>Package</th><th>Duration</th><th>Frames</th><th>Avg FPS</th><th>1% low</th><th>0.1% low</th><th>Median</th><th>P90</th><th>P95</th><th>P99</th><th>Jank</th><th>Big jank</th><th>Longest hitch</th><th>Below target</th></tr>\n");
+    for app in &report.apps {
+        let summary = &app.summary;
+        let _ = writeln!(
+            out,
+            "<tr><td>{}</td><td class=\"text\">{}</td><td>{}</td><td>{}</td><td>{:.2}</td><td>{:.2}</td><td>{:.2}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.1}%</td></tr>",
+            app.pid,
+            escape(app.package.as_deref().unwrap_or("-")),
+            format_secs(app.duration),
+            summary.frames,
+            summary.average_fps,
+            summary.low_1_percent_fps,
+            summary.low_0_1_percent_fps,
+            format_ms(summary.median),
+            format_ms(summary.p90),
+            format_ms(summary.p95),
+            format_ms(summary.p99),
+            app.janks.jank,
+            app.janks.big_jank,
+            app.longest_hitch
+                .map_or_else(|| "-".to_string(), |hitch| format_ms(hitch.frametime)),
+            below_target_percent(app),
+        );
+    }
+    out.push_str("</table>\n");
+
+    for app in &report.apps {
+        render_app(&mut out, report, app);
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn render_app(out: &mut String, report: &Report, app: &AppReport) {
+    let _ = writeln!(
+        out,
+        "<h2>{} ({})</h2>\n<ul>",
+        escape(app.package.as_deref().unwrap_or("pid")),
+        app.pid
+    );
+    if let Some(hitch) = app.longest_hitch {
+        let _ = writeln!(
+            out,
+            "<li>Longest hitch: {} at {} (<code>CLOCK_MONOTONIC</code> {} ns)</li>",
+            format_ms(hitch.frametime),
+            format_secs(hitch.offset),
+            hitch.timestamp_ns
+        );
+    }
+    let _ = writeln!(
+        out,
+        "<li>Time below {:.1} fps: {} ({:.1}%)</li>",
+        report.target_fps,
+        format_secs(app.time_below_target),
+        below_target_percent(app)
+    );
+    let _ = writeln!(
+        out,
+        "<li>Frametime: min {}, max {}, std dev {}</li>\n</ul>",
+        format_ms(app.summary.min),
+        format_ms(app.summary.max),
+        format_ms(app.summary.std_dev)
+    );
+
+    render_chart(out, report, app);
+}
+
+/// Line chart of the fps over time, with the target fps dashed
+fn render_chart(out: &mut String, report: &Report, app: &AppReport) {
+    let Some(last) = app.fps_over_time.last() else {
+        return;
+    };
+
+    let end = (last.offset + report.interval).as_secs_f64();
+    let max_fps = app
+        .fps_over_time
+        .iter()
+        .map(|sample| sample.fps)
+        .fold(report.target_fps, f64::max)
+        * 1.1;
+    let plot_width = CHART_MARGIN.mul_add(-2.0, CHART_WIDTH);
+    let plot_height = CHART_MARGIN.mul_add(-2.0, CHART_HEIGHT);
+    let x = |offset: Duration| (offset.as_secs_f64() / end).mul_add(plot_width, CHART_MARGIN);
+    let y = |fps: f64| (fps / max_fps).mul_add(-plot_height, CHART_MARGIN + plot_height);
+
+    let _ = writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{CHART_WIDTH}\" height=\"{CHART_HEIGHT}\" viewBox=\"0 0 {CHART_WIDTH} {CHART_HEIGHT}\">"
+    );
+
+    let bottom = CHART_MARGIN + plot_height;
+    let right = CHART_MARGIN + plot_width;
+    let _ = writeln!(
+        out,
+        "<line class=\"axis\" x1=\"{CHART_MARGIN}\" y1=\"{bottom}\" x2=\"{right}\" y2=\"{bottom}\"/>\n<line class=\"axis\" x1=\"{CHART_MARGIN}\" y1=\"{CHART_MARGIN}\" x2=\"{CHART_MARGIN}\" y2=\"{bottom}\"/>"
+    );
+    let target = y(report.target_fps);
+    let _ = writeln!(
+        out,
+        "<line class=\"target\" x1=\"{CHART_MARGIN}\" y1=\"{target:.1}\" x2=\"{right}\" y2=\"{target:.1}\"/>\n<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{:.0}</text>",
+        CHART_MARGIN - 4.0,
+        target + 4.0,
+        report.target_fps
+    );
+    let _ = writeln!(
+        out,
+        "<text x=\"{CHART_MARGIN}\" y=\"{:.1}\">0 s</text>\n<text x=\"{right}\" y=\"{:.1}\" text-anchor=\"end\">{end:.0} s</text>\n<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">0</text>",
+        bottom + 16.0,
+        bottom + 16.0,
+        CHART_MARGIN - 4.0,
+        bottom + 4.0,
+    );
+
+    // every sample is drawn in the middle of its interval
+    let points = app
+        .fps_over_time
+        .iter()
+        .map(|sample| {
+            format!(
+                "{:.1},{:.1}",
+                x(sample.offset + report.interval / 2),
+                y(sample.fps)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    let _ = writeln!(out, "<polyline class=\"fps\" points=\"{points}\"/>\n</svg>");
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,118 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::fmt::Write;
+
+use super::{
+    AppReport, Report, below_target_percent, format_ms, format_secs, format_unix_ms, title,
+};
+
+pub fn render(report: &Report) -> String {
+    let mut out = String::new();
+    let metadata = &report.metadata;
+
+    let _ = writeln!(out, "# Frame report: {}\n", title(report));
+    if !metadata.device.is_empty() {
+        let _ = writeln!(out, "- Device: {}", metadata.device);
+    }
+    if metadata.start_unix_ms != 0 {
+        let _ = writeln!(out, "- Started: {}", format_unix_ms(metadata.start_unix_ms));
+    }
+    if let Some(refresh_rate) = metadata.refresh_rate {
+        let _ = writeln!(out, "- Refresh rate: {refresh_rate:.2} Hz");
+    }
+    let _ = writeln!(out, "- Target: {:.1} fps\n", report.target_fps);
+
+    out.push_str("## Summary\n\n");
+    out.push_str("| PID | Package | Duration | Frames | Avg FPS | 1% low | 0.1% low | Median | P90 | P95 | P99 | Jank | Big jank | Longest hitch | Below target |\n");
+    out.push_str("|---:|---|---:|---:|---:|---:|---:|---:|---:|---:|---:|---:|---:|---:|---:|\n");
+    for app in &report.apps {
+        let summary = &app.summary;
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} | {:.2} | {:.2} | {:.2} | {} | {} | {} | {} | {} | {} | {} | {:.1}% |",
+            app.pid,
+            app.package.as_deref().unwrap_or("-"),
+            format_secs(app.duration),
+            summary.frames,
+            summary.average_fps,
+            summary.low_1_percent_fps,
+            summary.low_0_1_percent_fps,
+            format_ms(summary.median),
+            format_ms(summary.p90),
+            format_ms(summary.p95),
+            format_ms(summary.p99),
+            app.janks.jank,
+            app.janks.big_jank,
+            app.longest_hitch
+                .map_or_else(|| "-".to_string(), |hitch| format_ms(hitch.frametime)),
+            below_target_percent(app),
+        );
+    }
+
+    for app in &report.apps {
+        render_app(&mut out, report, app);
+    }
+
+    out
+}
+
+fn render_app(out: &mut String, report: &Report, app: &AppReport) {
+    let _ = writeln!(
+        out,
+        "\n## {} ({})\n",
+        app.package.as_deref().unwrap_or("pid"),
+        app.pid
+    );
+
+    if let Some(hitch) = app.longest_hitch {
+        let _ = writeln!(
+            out,
+            "- Longest hitch: {} at {} (`CLOCK_MONOTONIC` {} ns)",
+            format_ms(hitch.frametime),
+            format_secs(hitch.offset),
+            hitch.timestamp_ns
+        );
+    }
+    let _ = writeln!(
+        out,
+        "- Time below {:.1} fps: {} ({:.1}%)",
+        report.target_fps,
+        format_secs(app.time_below_target),
+        below_target_percent(app)
+    );
+    let _ = writeln!(
+        out,
+        "- Frametime: min {}, max {}, std dev {}",
+        format_ms(app.summary.min),
+        format_ms(app.summary.max),
+        format_ms(app.summary.std_dev)
+    );
+
+    out.push_str("\n<details>\n<summary>FPS over time</summary>\n\n");
+    out.push_str("| Time | FPS |\n|---:|---:|\n");
+    for sample in &app.fps_over_time {
+        let _ = writeln!(
+            out,
+            "| {} | {:.2} |",
+            format_secs(sample.offset),
+            sample.fps
+        );
+    }
+    out.push_str("\n</details>\n");
+}
@@ -0,0 +1,176 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Sources of frame signals
+//!
+//! The [`Analyzer`](crate::Analyzer) reads the ebpf uprobes by default, any other [`FrameSource`] can be
+//! plugged in with [`Analyzer::with_source`](crate::Analyzer::with_source), e.g. a [replay](crate::replay) or a [`MemorySource`] in tests
+//!
+//! # Examples
+//!
+//! A source generating a frame every 10ms with a timerfd
+//!
+//! ```
+//! use std::{collections::VecDeque, os::unix::io::RawFd};
+//!
+//! use frame_analyzer::{AnalyzerError, FrameSignal, source::FrameSource};
+//!
+//! struct Metronome {
+//!     timerfd: RawFd,
+//!     now_ns: u64,
+//! }
+//!
+//! impl FrameSource for Metronome {
+//!     fn raw_fd(&mut self) -> Result<RawFd, AnalyzerError> {
+//!         Ok(self.timerfd)
+//!     }
+//!
+//!     fn drain(&mut self, signals: &mut VecDeque<FrameSignal>) -> Result<(), AnalyzerError> {
+//!         let mut expirations = [0u8; 8];
+//!         if unsafe { libc::read(self.timerfd, expirations.as_mut_ptr().cast(), 8) } == 8 {
+//!             for _ in 0..u64::from_ne_bytes(expirations) {
+//!                 self.now_ns += 10_000_000;
+//!                 signals.push_back(FrameSignal::new(self.now_ns, 0x7f00, 0, 42));
+//!             }
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//! ```
+mod memory;
+
+use std::{collections::HashMap, collections::VecDeque, os::unix::io::RawFd};
+
+use frame_analyzer_ebpf_common::FrameSignal;
+use mio::{Interest, Registry, Token, unix::SourceFd};
+
+pub use memory::{MemorySender, MemorySource};
+
+use crate::{Pid, error::Result, uprobe::UprobeHandler};
+
+/// Token of the global uprobe or the external source
+pub(crate) const GLOBAL_TOKEN: Token = Token(usize::MAX);
+
+/// A pollable source of frame signals
+///
+/// The analyzer waits for [`FrameSource::raw_fd`] to become readable, then calls [`FrameSource::drain`].
+/// The file descriptor is registered edge-triggered again after every wakeup
+pub trait FrameSource: Send {
+    /// A file descriptor that becomes readable when signals are available
+    ///
+    /// # Errors
+    ///
+    /// The source is not attached if this fails
+    fn raw_fd(&mut self) -> Result<RawFd>;
+
+    /// Move all available signals into `signals`
+    ///
+    /// # Errors
+    ///
+    /// The error is returned by [`Analyzer::recv_result`](crate::Analyzer::recv_result) & [`Analyzer::try_recv`](crate::Analyzer::try_recv),
+    /// the other receive methods ignore it. The signals pushed before it are still analyzed
+    fn drain(&mut self, signals: &mut VecDeque<FrameSignal>) -> Result<()>;
+
+    /// Whether the source will never produce a signal again, e.g. a replay reached the end of the trace
+    fn is_exhausted(&self) -> bool {
+        false
+    }
+}
+
+/// The sources an analyzer polls
+pub(crate) enum Sources {
+    /// The ebpf uprobes, one per app and / or the global one
+    Uprobe {
+        per_app: HashMap<Pid, UprobeHandler>,
+        global: Option<Box<UprobeHandler>>,
+    },
+    /// A single source providing the signals of every app, e.g. a replay
+    External(Box<dyn FrameSource>),
+}
+
+impl Sources {
+    pub fn uprobe() -> Self {
+        Self::Uprobe {
+            per_app: HashMap::new(),
+            global: None,
+        }
+    }
+
+    /// The uprobe serving `pid`, if any
+    pub fn uprobe_of(&mut self, pid: Pid) -> Option<&mut UprobeHandler> {
+        match self {
+            Self::Uprobe { per_app, global } => per_app.get_mut(&pid).or(global.as_deref_mut()),
+            Self::External(_) => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, token: Token) -> Option<&mut dyn FrameSource> {
+        match self {
+            Self::Uprobe { per_app, global } => {
+                if token == GLOBAL_TOKEN {
+                    global
+                        .as_deref_mut()
+                        .map(|global| global as &mut dyn FrameSource)
+                } else {
+                    let Token(pid) = token;
+                    per_app
+                        .get_mut(&(pid as Pid))
+                        .map(|uprobe| uprobe as &mut dyn FrameSource)
+                }
+            }
+            Self::External(source) => (token == GLOBAL_TOKEN).then_some(source.as_mut()),
+        }
+    }
+
+    pub fn register(&mut self, registry: &Registry) -> Result<()> {
+        match self {
+            Self::Uprobe { per_app, global } => {
+                for (pid, uprobe) in per_app {
+                    register(registry, uprobe, Token(*pid as usize))?;
+                }
+
+                if let Some(global) = global {
+                    register(registry, &mut **global, GLOBAL_TOKEN)?;
+                }
+            }
+            Self::External(source) => register(registry, source.as_mut(), GLOBAL_TOKEN)?,
+        }
+
+        Ok(())
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        match self {
+            Self::Uprobe { .. } => false,
+            Self::External(source) => source.is_exhausted(),
+        }
+    }
+}
+
+/// Re-arm the edge-triggered registration of a source, or register a new one
+fn register(registry: &Registry, source: &mut dyn FrameSource, token: Token) -> Result<()> {
+    let fd = source.raw_fd()?;
+    if registry
+        .reregister(&mut SourceFd(&fd), token, Interest::READABLE)
+        .is_err()
+    {
+        registry.register(&mut SourceFd(&fd), token, Interest::READABLE)?;
+    }
+
+    Ok(())
+}
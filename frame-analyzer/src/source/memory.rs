@@ -0,0 +1,215 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{
+    collections::VecDeque,
+    io,
+    os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use frame_analyzer_ebpf_common::FrameSignal;
+
+use super::FrameSource;
+use crate::error::Result;
+
+struct Shared {
+    signals: Mutex<VecDeque<FrameSignal>>,
+    senders: AtomicUsize,
+    eventfd: OwnedFd,
+}
+
+impl Shared {
+    fn notify(&self) {
+        let one = 1u64.to_ne_bytes();
+        let _ = unsafe { libc::write(self.eventfd.as_raw_fd(), one.as_ptr().cast(), one.len()) };
+    }
+}
+
+/// An in-memory [`FrameSource`], fed by its [`MemorySender`]s or with a fixed list of signals
+///
+/// It is exhausted once all its signals are drained and every [`MemorySender`] is dropped
+///
+/// # Examples
+///
+/// ```
+/// use std::thread;
+///
+/// use frame_analyzer::{Analyzer, FrameSignal, source::MemorySource};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let (source, sender) = MemorySource::new()?;
+/// let mut analyzer = Analyzer::with_source(source)?;
+/// analyzer.attach_app(42)?;
+///
+/// thread::spawn(move || {
+///     for i in 0..=10 {
+///         sender.send(FrameSignal::new(i * 10_000_000, 0x7f00, 0, 42));
+///     }
+/// });
+///
+/// let mut frames = 0;
+/// while !analyzer.is_exhausted() {
+///     if let Some(event) = analyzer.recv_event() {
+///         assert_eq!(event.fps(), 100.0);
+///         frames += 1;
+///     }
+/// }
+/// assert_eq!(frames, 10);
+/// assert!(analyzer.is_exhausted());
+/// # Ok(())
+/// # }
+/// ```
+pub struct MemorySource {
+    shared: Arc<Shared>,
+}
+
+/// Sends signals to a [`MemorySource`], can be cloned and sent to other threads
+pub struct MemorySender {
+    shared: Arc<Shared>,
+}
+
+impl MemorySource {
+    /// Create an empty source and its sender
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if the eventfd cannot be created
+    pub fn new() -> Result<(Self, MemorySender)> {
+        let source = Self::from_signals([])?;
+        source.shared.senders.store(1, Ordering::Release);
+        let sender = MemorySender {
+            shared: source.shared.clone(),
+        };
+
+        Ok((source, sender))
+    }
+
+    /// Create a source providing `signals`, exhausted once they are drained
+    ///
+    /// # Errors
+    ///
+    /// `IOError` if the eventfd cannot be created
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use frame_analyzer::{Analyzer, FrameSignal, source::MemorySource};
+    ///
+    /// # fn main() -> anyhow::Result<()> {
+    /// // two surfaces, the analyzer follows the busier one
+    /// let signals = (0..100u64).map(|i| {
+    ///     let surface = if i % 3 == 0 { 0xa } else { 0xb };
+    ///     FrameSignal::new(i * 5_000_000, surface, 0, 42)
+    /// });
+    /// let mut analyzer = Analyzer::with_source(MemorySource::from_signals(signals)?)?;
+    /// analyzer.attach_app(42)?;
+    ///
+    /// let mut last = None;
+    /// while !analyzer.is_exhausted() {
+    ///     last = analyzer.recv_event().or(last);
+    /// }
+    /// assert_eq!(last.map(|event| event.surface), Some(0xb));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_signals<I: IntoIterator<Item = FrameSignal>>(signals: I) -> Result<Self> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        let shared = Arc::new(Shared {
+            signals: Mutex::new(signals.into_iter().collect()),
+            senders: AtomicUsize::new(0),
+            eventfd: unsafe { OwnedFd::from_raw_fd(fd) },
+        });
+        shared.notify();
+
+        Ok(Self { shared })
+    }
+}
+
+impl MemorySender {
+    /// Send a signal to the source
+    pub fn send(&self, signal: FrameSignal) {
+        self.shared
+            .signals
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push_back(signal);
+        self.shared.notify();
+    }
+}
+
+impl Clone for MemorySender {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::AcqRel);
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for MemorySender {
+    fn drop(&mut self) {
+        // wake up the analyzer, it may be exhausted now
+        self.shared.senders.fetch_sub(1, Ordering::AcqRel);
+        self.shared.notify();
+    }
+}
+
+impl FrameSource for MemorySource {
+    fn raw_fd(&mut self) -> Result<RawFd> {
+        Ok(self.shared.eventfd.as_raw_fd())
+    }
+
+    fn drain(&mut self, signals: &mut VecDeque<FrameSignal>) -> Result<()> {
+        let mut counter = [0u8; 8];
+        let _ = unsafe {
+            libc::read(
+                self.shared.eventfd.as_raw_fd(),
+                counter.as_mut_ptr().cast(),
+                counter.len(),
+            )
+        };
+
+        signals.append(
+            &mut self
+                .shared
+                .signals
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+
+        Ok(())
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.shared.senders.load(Ordering::Acquire) == 0
+            && self
+                .shared
+                .signals
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_empty()
+    }
+}
@@ -0,0 +1,299 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Frame statistics: fps, percentiles and lows over a sliding window
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use frame_analyzer::stats::{FrameStats, Window};
+//!
+//! let mut stats = FrameStats::new(Window::Frames(120));
+//! for _ in 0..99 {
+//!     stats.push_frametime(Duration::from_millis(10));
+//! }
+//! stats.push_frametime(Duration::from_millis(100)); // a hitch
+//!
+//! let summary = stats.summary().unwrap();
+//! assert_eq!(summary.frames, 100);
+//! assert_eq!(summary.median, Duration::from_millis(10));
+//! assert_eq!(summary.max, Duration::from_millis(100));
+//! assert_eq!(summary.low_1_percent_fps, 10.0);
+//! ```
+use std::{collections::VecDeque, time::Duration};
+
+use crate::FrameEvent;
+
+/// The range of frames a [`FrameStats`] keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Window {
+    /// The latest `n` frames
+    Frames(usize),
+    /// The latest frames whose frametimes add up to at most this duration, the newest frame is always kept
+    Duration(Duration),
+}
+
+/// A snapshot of the statistics of a [`FrameStats`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StatsSummary {
+    /// Number of frames in the window
+    pub frames: usize,
+    /// Sum of the frametimes in the window
+    pub duration: Duration,
+    /// Frames per second over the window
+    pub average_fps: f64,
+    /// The shortest frametime
+    pub min: Duration,
+    /// The longest frametime
+    pub max: Duration,
+    /// The 50th percentile frametime
+    pub median: Duration,
+    /// The 90th percentile frametime
+    pub p90: Duration,
+    /// The 95th percentile frametime
+    pub p95: Duration,
+    /// The 99th percentile frametime
+    pub p99: Duration,
+    /// Fps of the slowest 1% frames, see [`FrameStats::low_fps`]
+    pub low_1_percent_fps: f64,
+    /// Fps of the slowest 0.1% frames, see [`FrameStats::low_fps`]
+    pub low_0_1_percent_fps: f64,
+    /// Standard deviation of the frametimes
+    pub std_dev: Duration,
+}
+
+/// Frame statistics accumulator over a sliding [`Window`]
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use frame_analyzer::stats::{FrameStats, Window};
+///
+/// // two seconds at 60 fps, only the latest second is kept
+/// let mut stats = FrameStats::new(Window::Duration(Duration::from_secs(1)));
+/// for _ in 0..120 {
+///     stats.push_frametime(Duration::from_micros(16_667));
+/// }
+/// assert_eq!(stats.len(), 59);
+///
+/// // frametimes of 1ms, 2ms ... 100ms
+/// let mut stats = FrameStats::new(Window::Frames(100));
+/// for ms in 1..=100 {
+///     stats.push_frametime(Duration::from_millis(ms));
+/// }
+/// assert_eq!(stats.percentile(90.0), Some(Duration::from_millis(90)));
+/// assert_eq!(stats.min(), Some(Duration::from_millis(1)));
+/// // the slowest 10% frames average 95.5ms
+/// assert_eq!(stats.low_fps(10.0), Some(10.0 / 0.955));
+/// ```
+#[derive(Debug, Clone)]
+pub struct FrameStats {
+    window: Window,
+    frametimes: VecDeque<Duration>,
+    total: Duration,
+}
+
+impl FrameStats {
+    /// Create an empty accumulator
+    #[must_use]
+    pub fn new(window: Window) -> Self {
+        let capacity = match window {
+            Window::Frames(frames) => frames,
+            Window::Duration(_) => 0,
+        };
+
+        Self {
+            window,
+            frametimes: VecDeque::with_capacity(capacity),
+            total: Duration::ZERO,
+        }
+    }
+
+    /// The window of this accumulator
+    #[must_use]
+    pub const fn window(&self) -> Window {
+        self.window
+    }
+
+    /// Feed a frame
+    pub fn push(&mut self, event: &FrameEvent) {
+        self.push_frametime(event.frametime);
+    }
+
+    /// Feed a raw frametime
+    pub fn push_frametime(&mut self, frametime: Duration) {
+        self.frametimes.push_back(frametime);
+        self.total += frametime;
+
+        match self.window {
+            Window::Frames(frames) => {
+                while self.frametimes.len() > frames.max(1) {
+                    self.pop_oldest();
+                }
+            }
+            Window::Duration(duration) => {
+                while self.total > duration && self.frametimes.len() > 1 {
+                    self.pop_oldest();
+                }
+            }
+        }
+    }
+
+    fn pop_oldest(&mut self) {
+        if let Some(frametime) = self.frametimes.pop_front() {
+            self.total -= frametime;
+        }
+    }
+
+    /// Drop all frames
+    pub fn clear(&mut self) {
+        self.frametimes.clear();
+        self.total = Duration::ZERO;
+    }
+
+    /// Number of frames in the window
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.frametimes.len()
+    }
+
+    /// Whether the window is empty
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.frametimes.is_empty()
+    }
+
+    /// An iterator over the frametimes in the window, oldest first
+    pub fn frametimes(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.frametimes.iter().copied()
+    }
+
+    /// Frames per second over the window, `None` if it is empty
+    #[must_use]
+    pub fn average_fps(&self) -> Option<f64> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.frametimes.len() as f64 / self.total.as_secs_f64())
+        }
+    }
+
+    /// The mean frametime, `None` if the window is empty
+    #[must_use]
+    pub fn mean(&self) -> Option<Duration> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.total / self.frametimes.len() as u32)
+        }
+    }
+
+    /// The shortest frametime
+    #[must_use]
+    pub fn min(&self) -> Option<Duration> {
+        self.frametimes.iter().copied().min()
+    }
+
+    /// The longest frametime
+    #[must_use]
+    pub fn max(&self) -> Option<Duration> {
+        self.frametimes.iter().copied().max()
+    }
+
+    /// A percentile (`0.0..=100.0`) of the frametimes, by the nearest-rank method
+    #[must_use]
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        percentile_of(&self.sorted(), percentile)
+    }
+
+    /// Fps of the slowest `percent`% frames, e.g. `low_fps(1.0)` for the "1% low"
+    ///
+    /// It is the average fps of the slowest `ceil(percent% * frames)` frames, at least one frame is counted
+    #[must_use]
+    pub fn low_fps(&self, percent: f64) -> Option<f64> {
+        low_fps_of(&self.sorted(), percent)
+    }
+
+    /// Standard deviation of the frametimes
+    #[must_use]
+    pub fn std_dev(&self) -> Option<Duration> {
+        let mean = self.mean()?.as_secs_f64();
+        let variance = self
+            .frametimes
+            .iter()
+            .map(|frametime| (frametime.as_secs_f64() - mean).powi(2))
+            .sum::<f64>()
+            / self.frametimes.len() as f64;
+
+        Some(Duration::from_secs_f64(variance.sqrt()))
+    }
+
+    /// Compute all statistics at once, sorting the window only one time
+    #[must_use]
+    pub fn summary(&self) -> Option<StatsSummary> {
+        let sorted = self.sorted();
+
+        Some(StatsSummary {
+            frames: sorted.len(),
+            duration: self.total,
+            average_fps: self.average_fps()?,
+            min: *sorted.first()?,
+            max: *sorted.last()?,
+            median: percentile_of(&sorted, 50.0)?,
+            p90: percentile_of(&sorted, 90.0)?,
+            p95: percentile_of(&sorted, 95.0)?,
+            p99: percentile_of(&sorted, 99.0)?,
+            low_1_percent_fps: low_fps_of(&sorted, 1.0)?,
+            low_0_1_percent_fps: low_fps_of(&sorted, 0.1)?,
+            std_dev: self.std_dev()?,
+        })
+    }
+
+    fn sorted(&self) -> Vec<Duration> {
+        let mut sorted: Vec<_> = self.frametimes.iter().copied().collect();
+        sorted.sort_unstable();
+        sorted
+    }
+}
+
+fn percentile_of(sorted: &[Duration], percentile: f64) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+
+    let rank = (percentile.clamp(0.0, 100.0) / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.clamp(1, sorted.len()) - 1).copied()
+}
+
+fn low_fps_of(sorted: &[Duration], percent: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+
+    let count = (percent.clamp(0.0, 100.0) / 100.0 * sorted.len() as f64).ceil() as usize;
+    let slowest = &sorted[sorted.len() - count.clamp(1, sorted.len())..];
+    let total: Duration = slowest.iter().sum();
+
+    Some(slowest.len() as f64 / total.as_secs_f64())
+}
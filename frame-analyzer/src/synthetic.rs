@@ -0,0 +1,320 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Generate synthetic frame signals from declarative scenarios
+//!
+//! The generation is deterministic for a given seed, so the analysis can be tested without any device,
+//! feed the signals to an [`Analyzer`](crate::Analyzer) with [`source`]
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use frame_analyzer::{
+//!     Analyzer,
+//!     jank::{JankDetector, JankRule},
+//!     stats::Window,
+//!     synthetic::{self, Jitter, Scenario},
+//! };
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! // 60 fps with a 50ms hitch every 60 frames, for 10 seconds
+//! let scenario = Scenario::new(42, 60.0)
+//!     .duration(Duration::from_secs(10))
+//!     .jitter(Jitter::Uniform(Duration::from_micros(500)))
+//!     .hitch(60, Duration::from_millis(50));
+//!
+//! let mut analyzer = Analyzer::with_source(synthetic::source(&[scenario])?)?;
+//! analyzer.attach_app(42)?;
+//!
+//! let period = Duration::from_secs(1) / 60;
+//! let mut detector = JankDetector::new(JankRule::vsync(period), Window::Frames(1000));
+//! while !analyzer.is_exhausted() {
+//!     if let Some(event) = analyzer.recv_event() {
+//!         detector.push(&event);
+//!     }
+//! }
+//!
+//! assert_eq!(detector.counts().big_jank, detector.counts().frames / 60);
+//! # Ok(())
+//! # }
+//! ```
+use std::time::Duration;
+
+use frame_analyzer_ebpf_common::FrameSignal;
+
+use crate::{Pid, error::Result, source::MemorySource};
+
+/// Shortest generated frametime, whatever the jitter
+const MIN_FRAMETIME: Duration = Duration::from_micros(100);
+
+/// Distribution of the deviation of every frametime from the target interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Jitter {
+    /// Every frame takes exactly the target interval
+    #[default]
+    None,
+    /// Uniformly distributed in `[-max, max]`
+    Uniform(Duration),
+    /// Normally distributed with this standard deviation
+    Normal(Duration),
+}
+
+/// The frames of an application, built like [`AnalyzerBuilder`](crate::AnalyzerBuilder)
+///
+/// The main surface draws at the target fps with jitter & hitches, the extra surfaces draw steadily at their own rate
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use frame_analyzer::synthetic::Scenario;
+///
+/// // a game switching from its loading surface to the game surface after 2s, then killed after 5s
+/// let scenario = Scenario::new(42, 120.0)
+///     .surface(0x1000)
+///     .switch_surface(Duration::from_secs(2), 0x2000)
+///     .exit(Duration::from_secs(5))
+///     .duration(Duration::from_secs(10));
+///
+/// let signals = scenario.signals();
+/// assert!(signals.iter().all(|signal| signal.ktime_ns <= 5_000_000_000));
+/// assert_eq!(signals.iter().filter(|signal| signal.buffer == 0x1000).count(), 241);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+    pid: Pid,
+    fps: f64,
+    duration: Duration,
+    start_ns: u64,
+    surface: usize,
+    jitter: Jitter,
+    hitch: Option<(usize, Duration)>,
+    extra_surfaces: Vec<(usize, f64)>,
+    switch: Option<(Duration, usize)>,
+    exit: Option<Duration>,
+    seed: u64,
+}
+
+impl Scenario {
+    /// An application drawing at `fps` for 1 second, on surface `0x7f00` without jitter
+    ///
+    /// # Panics
+    ///
+    /// If `fps` is not a positive finite number
+    #[must_use]
+    pub const fn new(pid: Pid, fps: f64) -> Self {
+        assert!(valid_fps(fps), "fps must be positive & finite");
+        Self {
+            pid,
+            fps,
+            duration: Duration::from_secs(1),
+            start_ns: 0,
+            surface: 0x7f00,
+            jitter: Jitter::None,
+            hitch: None,
+            extra_surfaces: Vec::new(),
+            switch: None,
+            exit: None,
+            seed: 0,
+        }
+    }
+
+    /// How long the scenario lasts
+    #[must_use]
+    pub const fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Timestamp of the first frame, `0` by default
+    #[must_use]
+    pub const fn start_ns(mut self, start_ns: u64) -> Self {
+        self.start_ns = start_ns;
+        self
+    }
+
+    /// The main surface
+    #[must_use]
+    pub const fn surface(mut self, surface: usize) -> Self {
+        self.surface = surface;
+        self
+    }
+
+    /// Deviation of the frametimes of the main surface, see [`Jitter`]
+    #[must_use]
+    pub const fn jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Add `extra` to every `every`th frametime of the main surface
+    #[must_use]
+    pub const fn hitch(mut self, every: usize, extra: Duration) -> Self {
+        self.hitch = Some((every, extra));
+        self
+    }
+
+    /// Add a surface drawing steadily at `fps` alongside the main one, e.g. an overlay
+    ///
+    /// # Panics
+    ///
+    /// If `fps` is not a positive finite number
+    #[must_use]
+    pub fn extra_surface(mut self, surface: usize, fps: f64) -> Self {
+        assert!(valid_fps(fps), "fps must be positive & finite");
+        self.extra_surfaces.push((surface, fps));
+        self
+    }
+
+    /// The main surface moves to `surface` at `at` after the start
+    #[must_use]
+    pub const fn switch_surface(mut self, at: Duration, surface: usize) -> Self {
+        self.switch = Some((at, surface));
+        self
+    }
+
+    /// The process exits at `at` after the start, no frame is generated after it
+    #[must_use]
+    pub const fn exit(mut self, at: Duration) -> Self {
+        self.exit = Some(at);
+        self
+    }
+
+    /// Seed of the jitter
+    #[must_use]
+    pub const fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Generate the signals of the scenario, ordered by timestamp
+    #[must_use]
+    pub fn signals(&self) -> Vec<FrameSignal> {
+        let end = self
+            .exit
+            .map_or(self.duration, |exit| exit.min(self.duration));
+        let mut signals = self.main_surface(end);
+
+        for &(surface, fps) in &self.extra_surfaces {
+            let interval = Duration::from_secs_f64(1.0 / fps);
+            let mut time = Duration::ZERO;
+            while time <= end {
+                signals.push(self.signal(time, surface));
+                time += interval;
+            }
+        }
+
+        signals.sort_by_key(|signal| signal.ktime_ns);
+        signals
+    }
+
+    fn main_surface(&self, end: Duration) -> Vec<FrameSignal> {
+        let interval = Duration::from_secs_f64(1.0 / self.fps);
+        let mut rng = SplitMix64(self.seed);
+        let mut signals = Vec::new();
+        let mut time = Duration::ZERO;
+        let mut frame = 0;
+
+        while time <= end {
+            let surface = match self.switch {
+                Some((at, surface)) if time >= at => surface,
+                _ => self.surface,
+            };
+            signals.push(self.signal(time, surface));
+
+            frame += 1;
+            let mut frametime = match self.jitter {
+                Jitter::None => interval.as_secs_f64(),
+                Jitter::Uniform(max) => max
+                    .as_secs_f64()
+                    .mul_add(rng.uniform().mul_add(2.0, -1.0), interval.as_secs_f64()),
+                Jitter::Normal(std_dev) => std_dev
+                    .as_secs_f64()
+                    .mul_add(rng.normal(), interval.as_secs_f64()),
+            };
+            if let Some((every, extra)) = self.hitch
+                && every > 0
+                && frame % every == 0
+            {
+                frametime += extra.as_secs_f64();
+            }
+
+            time += Duration::from_secs_f64(frametime.max(MIN_FRAMETIME.as_secs_f64()));
+        }
+
+        signals
+    }
+
+    const fn signal(&self, time: Duration, surface: usize) -> FrameSignal {
+        FrameSignal::new(
+            self.start_ns + time.as_nanos() as u64,
+            surface,
+            0,
+            self.pid as u32,
+        )
+    }
+}
+
+const fn valid_fps(fps: f64) -> bool {
+    fps.is_finite() && fps > 0.0
+}
+
+/// Generate the signals of several scenarios, merged by timestamp
+#[must_use]
+pub fn signals(scenarios: &[Scenario]) -> Vec<FrameSignal> {
+    let mut signals: Vec<_> = scenarios.iter().flat_map(Scenario::signals).collect();
+    signals.sort_by_key(|signal| signal.ktime_ns);
+    signals
+}
+
+/// A [`MemorySource`] providing the signals of several scenarios, exhausted once they are drained
+///
+/// # Errors
+///
+/// See [`MemorySource::from_signals`]
+pub fn source(scenarios: &[Scenario]) -> Result<MemorySource> {
+    MemorySource::from_signals(signals(scenarios))
+}
+
+/// Small deterministic generator, good enough for jitter
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    const fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`
+    fn uniform(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal, Box-Muller transform
+    fn normal(&mut self) -> f64 {
+        let u1 = 1.0 - self.uniform();
+        let u2 = self.uniform();
+        (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    }
+}
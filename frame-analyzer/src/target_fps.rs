@@ -0,0 +1,228 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Infer the frame rate cap of an application from its recent frametimes
+//!
+//! Frame-rate-capped apps produce frametimes clustered around the interval of their cap, so every frame is matched to
+//! the nearest candidate rate and the candidate matching most of the recent frames is the inferred cap
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use frame_analyzer::target_fps::TargetFpsEstimator;
+//!
+//! let mut estimator = TargetFpsEstimator::new();
+//! let mut changes = Vec::new();
+//!
+//! // a game capped at 60 fps with a few dips...
+//! for i in 0..120 {
+//!     let frametime = if i % 10 == 0 { 33_333 } else { 16_667 };
+//!     changes.extend(estimator.push_frametime(Duration::from_micros(frametime)));
+//! }
+//! assert_eq!(estimator.estimate().unwrap().fps, 60);
+//!
+//! // ...switching to 30 fps
+//! for _ in 0..120 {
+//!     changes.extend(estimator.push_frametime(Duration::from_micros(33_333)));
+//! }
+//! assert_eq!(estimator.estimate().unwrap().fps, 30);
+//! assert_eq!(changes.len(), 2);
+//! assert_eq!(changes[1].previous.unwrap().fps, 60);
+//! ```
+use std::{collections::VecDeque, time::Duration};
+
+use crate::FrameEvent;
+
+/// Frame rate caps commonly used by games
+pub const DEFAULT_CANDIDATES: &[u32] = &[30, 45, 60, 90, 120, 144];
+
+/// Default number of recent frames the estimate is based on
+pub const DEFAULT_WINDOW: usize = 120;
+
+/// A frame only matches a candidate if its frametime is within this fraction of the candidate interval
+const TOLERANCE: f64 = 0.1;
+
+/// An inferred frame rate cap
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TargetFpsEstimate {
+    /// The inferred cap
+    pub fps: u32,
+    /// Fraction of the recent frames matching the cap, `0.0..=1.0`
+    pub confidence: f64,
+}
+
+impl TargetFpsEstimate {
+    /// The frame interval of the cap
+    #[must_use]
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(1) / self.fps
+    }
+}
+
+/// Emitted by [`TargetFpsEstimator`] when the inferred cap changes
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TargetFpsChange {
+    /// The cap inferred before, `None` for the first estimate
+    pub previous: Option<TargetFpsEstimate>,
+    /// The newly inferred cap
+    pub current: TargetFpsEstimate,
+}
+
+/// Infers the frame rate cap of one frame stream (e.g. one pid)
+#[derive(Debug, Clone)]
+pub struct TargetFpsEstimator {
+    candidates: Vec<u32>,
+    window: usize,
+    min_confidence: f64,
+    frames: VecDeque<Option<usize>>,
+    counts: Vec<usize>,
+    current: Option<TargetFpsEstimate>,
+}
+
+impl Default for TargetFpsEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TargetFpsEstimator {
+    /// An estimator over [`DEFAULT_CANDIDATES`] and the latest [`DEFAULT_WINDOW`] frames
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_candidates(DEFAULT_CANDIDATES, DEFAULT_WINDOW)
+    }
+
+    /// An estimator over custom candidate rates and the latest `window` frames
+    ///
+    /// Zero rates are ignored
+    #[must_use]
+    pub fn with_candidates(candidates: &[u32], window: usize) -> Self {
+        let mut candidates: Vec<_> = candidates.iter().copied().filter(|fps| *fps > 0).collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        Self {
+            counts: vec![0; candidates.len()],
+            candidates,
+            window: window.max(1),
+            min_confidence: 0.5,
+            frames: VecDeque::with_capacity(window),
+            current: None,
+        }
+    }
+
+    /// Minimum confidence for a cap to be reported, `0.5` by default
+    pub const fn set_min_confidence(&mut self, min_confidence: f64) {
+        self.min_confidence = min_confidence;
+    }
+
+    /// The currently inferred cap, `None` if no candidate has been confident enough yet
+    #[must_use]
+    pub const fn estimate(&self) -> Option<TargetFpsEstimate> {
+        self.current
+    }
+
+    /// Feed a frame, returns the change if the inferred cap changed
+    pub fn push(&mut self, event: &FrameEvent) -> Option<TargetFpsChange> {
+        self.push_frametime(event.frametime)
+    }
+
+    /// Feed a raw frametime, returns the change if the inferred cap changed
+    pub fn push_frametime(&mut self, frametime: Duration) -> Option<TargetFpsChange> {
+        let candidate = self.match_candidate(frametime);
+        if let Some(candidate) = candidate {
+            self.counts[candidate] += 1;
+        }
+        self.frames.push_back(candidate);
+
+        while self.frames.len() > self.window {
+            if let Some(Some(candidate)) = self.frames.pop_front() {
+                self.counts[candidate] -= 1;
+            }
+        }
+
+        // wait for a full window before estimating
+        if self.frames.len() < self.window {
+            self.refresh_confidence();
+            return None;
+        }
+
+        let (best, count) = self
+            .counts
+            .iter()
+            .copied()
+            .enumerate()
+            .max_by_key(|(_, count)| *count)?;
+        let fps = self.candidates[best];
+        let confidence = count as f64 / self.frames.len() as f64;
+
+        if self.current.is_some_and(|current| current.fps == fps)
+            || confidence < self.min_confidence
+        {
+            self.refresh_confidence();
+            return None;
+        }
+
+        let previous = self.current;
+        let current = TargetFpsEstimate { fps, confidence };
+        self.current = Some(current);
+
+        Some(TargetFpsChange { previous, current })
+    }
+
+    /// Drop all frames and the current estimate
+    pub fn reset(&mut self) {
+        self.frames.clear();
+        self.counts.fill(0);
+        self.current = None;
+    }
+
+    fn refresh_confidence(&mut self) {
+        let Some(fps) = self.current.map(|current| current.fps) else {
+            return;
+        };
+
+        let count = self
+            .candidates
+            .iter()
+            .position(|candidate| *candidate == fps)
+            .map_or(0, |index| self.counts[index]);
+        let confidence = count as f64 / self.frames.len().max(1) as f64;
+
+        if let Some(ref mut current) = self.current {
+            current.confidence = confidence;
+        }
+    }
+
+    fn match_candidate(&self, frametime: Duration) -> Option<usize> {
+        let frametime = frametime.as_secs_f64();
+
+        self.candidates
+            .iter()
+            .map(|fps| 1.0 / f64::from(*fps))
+            .enumerate()
+            .map(|(index, interval)| (index, (frametime - interval).abs() / interval))
+            .filter(|(_, error)| *error <= TOLERANCE)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(index, _)| index)
+    }
+}
@@ -0,0 +1,344 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Record frame signals to a binary trace file and read them back
+//!
+//! The file starts with a header holding the format version and the [`SessionMetadata`], followed by append-only chunks of records.
+//! Every chunk carries its own checksum and can be decoded on its own, a crashed recorder loses at most the chunk it was filling
+//!
+//! # Layout
+//!
+//! All integers are little-endian
+//!
+//! - header: magic `FATRACE\0`, version `u16`, reserved `u16`, metadata length `u32`, metadata, crc32 of the metadata `u32`
+//! - metadata: pair count `u16`, then `(key, value)` string pairs, each string as length `u16` + utf-8 bytes
+//! - chunk: magic `CHNK`, record count `u32`, payload length `u32`, crc32 of the payload `u32`, payload
+//! - record: zigzag varint of the timestamp delta to the previous record of the chunk (to 0 for the first one),
+//!   then varints of pid, surface and frametime (`0` if it was not computed by the ebpf program)
+//!
+//! # Examples
+//!
+//! ```
+//! use frame_analyzer::trace::{SessionMetadata, TraceReader, TraceRecord, TraceWriter};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let path = std::env::temp_dir().join("frame-analyzer-doc.fatrace");
+//! let metadata = SessionMetadata {
+//!     device: "pixel".into(),
+//!     package: "com.example.game".into(),
+//!     refresh_rate: Some(120.0),
+//!     ..SessionMetadata::default()
+//! };
+//!
+//! let mut writer = TraceWriter::create(&path, &metadata)?;
+//! for i in 0..1000 {
+//!     writer.write(&TraceRecord::new(1_000_000 + i * 8_333_333, 42, 0x7f00, 0))?;
+//! }
+//! writer.finish()?;
+//!
+//! let reader = TraceReader::open(&path)?;
+//! assert_eq!(reader.metadata(), &metadata);
+//! let records = reader.collect::<Result<Vec<_>, _>>()?;
+//! assert_eq!(records.len(), 1000);
+//! assert_eq!(records[999].timestamp_ns, 1_000_000 + 999 * 8_333_333);
+//! # std::fs::remove_file(&path)?;
+//! # Ok(())
+//! # }
+//! ```
+mod reader;
+mod writer;
+
+use std::io::{self, Read};
+
+use frame_analyzer_ebpf_common::FrameSignal;
+
+pub use reader::TraceReader;
+pub use writer::TraceWriter;
+
+use crate::{Pid, error::AnalyzerError};
+
+/// Magic bytes at the start of a trace file
+pub const MAGIC: [u8; 8] = *b"FATRACE\0";
+/// Version of the format written by [`TraceWriter`]
+pub const VERSION: u16 = 1;
+/// Records per chunk, a chunk is written once it is full
+pub const CHUNK_RECORDS: usize = 256;
+/// Upper bound of the encoded [`SessionMetadata`], a trace claiming a longer one is rejected before it is allocated
+pub const MAX_METADATA_LEN: usize = 1 << 20;
+
+/// Key prefix of the package names of the recorded pids in [`SessionMetadata::extra`]
+const PACKAGE_KEY: &str = "package.";
+const CHUNK_MAGIC: [u8; 4] = *b"CHNK";
+const CHUNK_HEADER_LEN: usize = 16;
+/// Upper bound of a chunk payload (4 varints of at most 10 bytes per record), a longer length can only come from a corrupted chunk header
+const MAX_CHUNK_LEN: usize = CHUNK_RECORDS * 4 * 10;
+
+/// Information about a recorded session
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SessionMetadata {
+    /// The recording device, e.g. its model
+    pub device: String,
+    /// Package name of the recorded application
+    pub package: String,
+    /// Display refresh rate in Hz, if known
+    pub refresh_rate: Option<f64>,
+    /// Start of the session in milliseconds since the unix epoch, `0` if unknown
+    pub start_unix_ms: u64,
+    /// Any other `(key, value)` pairs
+    pub extra: Vec<(String, String)>,
+}
+
+/// A recorded frame signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceRecord {
+    /// When the frame was queued, in nanoseconds of `CLOCK_MONOTONIC`
+    pub timestamp_ns: u64,
+    /// The pid of the application
+    pub pid: Pid,
+    /// The surface the frame was queued to
+    pub surface: usize,
+    /// Time since the previous frame of the surface as computed by the ebpf program, `0` if it was not computed
+    pub frametime_ns: u64,
+}
+
+impl TraceRecord {
+    /// Create a record
+    #[must_use]
+    pub const fn new(timestamp_ns: u64, pid: Pid, surface: usize, frametime_ns: u64) -> Self {
+        Self {
+            timestamp_ns,
+            pid,
+            surface,
+            frametime_ns,
+        }
+    }
+}
+
+impl From<&FrameSignal> for TraceRecord {
+    fn from(signal: &FrameSignal) -> Self {
+        Self::new(
+            signal.ktime_ns,
+            signal.pid as Pid,
+            signal.buffer,
+            signal.frametime_ns,
+        )
+    }
+}
+
+impl From<&TraceRecord> for FrameSignal {
+    fn from(record: &TraceRecord) -> Self {
+        Self::new(
+            record.timestamp_ns,
+            record.surface,
+            record.frametime_ns,
+            record.pid as u32,
+        )
+    }
+}
+
+impl SessionMetadata {
+    /// Record the package name of a pid, for sessions with several apps
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use frame_analyzer::trace::SessionMetadata;
+    ///
+    /// let mut metadata = SessionMetadata::default();
+    /// metadata.set_package_name(42, "com.example.game");
+    /// assert_eq!(metadata.package_name(42), Some("com.example.game"));
+    /// assert_eq!(metadata.package_name(43), None);
+    ///
+    /// // the package of a single app session is the package of its pids
+    /// let metadata = SessionMetadata {
+    ///     package: "com.example.video".into(),
+    ///     ..SessionMetadata::default()
+    /// };
+    /// assert_eq!(metadata.package_name(43), Some("com.example.video"));
+    /// ```
+    pub fn set_package_name<S: Into<String>>(&mut self, pid: Pid, package: S) {
+        let key = format!("{PACKAGE_KEY}{pid}");
+        let package = package.into();
+        match self.extra.iter_mut().find(|(name, _)| *name == key) {
+            Some((_, value)) => *value = package,
+            None => self.extra.push((key, package)),
+        }
+    }
+
+    /// The package name of a recorded pid, falls back to [`SessionMetadata::package`] if it names a single package
+    #[must_use]
+    pub fn package_name(&self, pid: Pid) -> Option<&str> {
+        let key = format!("{PACKAGE_KEY}{pid}");
+        self.extra
+            .iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, package)| package.as_str())
+            .or_else(|| {
+                (!self.package.is_empty() && !self.package.contains(','))
+                    .then_some(self.package.as_str())
+            })
+    }
+
+    /// Fails rather than truncating, a cut string or pair list would be unreadable or silently lost
+    fn encode(&self) -> Result<Vec<u8>, AnalyzerError> {
+        let mut pairs: Vec<(&str, String)> = vec![
+            ("device", self.device.clone()),
+            ("package", self.package.clone()),
+            ("start_unix_ms", self.start_unix_ms.to_string()),
+        ];
+        if let Some(refresh_rate) = self.refresh_rate {
+            pairs.push(("refresh_rate", refresh_rate.to_string()));
+        }
+        pairs.extend(
+            self.extra
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.clone())),
+        );
+
+        let count = u16::try_from(pairs.len())
+            .map_err(|_| AnalyzerError::InvalidTrace("too many metadata pairs"))?;
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&count.to_le_bytes());
+        for (key, value) in pairs {
+            put_str(&mut buf, key)?;
+            put_str(&mut buf, &value)?;
+        }
+        Ok(buf)
+    }
+
+    fn decode(mut buf: &[u8]) -> Result<Self, AnalyzerError> {
+        let mut metadata = Self::default();
+        let count = u16::from_le_bytes(take(&mut buf)?);
+
+        for _ in 0..count {
+            let key = take_str(&mut buf)?;
+            let value = take_str(&mut buf)?;
+            match key.as_str() {
+                "device" => metadata.device = value,
+                "package" => metadata.package = value,
+                "start_unix_ms" => metadata.start_unix_ms = value.parse().unwrap_or_default(),
+                "refresh_rate" => metadata.refresh_rate = value.parse().ok(),
+                _ => metadata.extra.push((key, value)),
+            }
+        }
+
+        Ok(metadata)
+    }
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &str) -> Result<(), AnalyzerError> {
+    let len = u16::try_from(s.len())
+        .map_err(|_| AnalyzerError::InvalidTrace("metadata string too long"))?;
+    buf.extend_from_slice(&len.to_le_bytes());
+    buf.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+fn take<const N: usize>(buf: &mut &[u8]) -> Result<[u8; N], AnalyzerError> {
+    let (head, tail) = buf
+        .split_first_chunk::<N>()
+        .ok_or(AnalyzerError::InvalidTrace("truncated metadata"))?;
+    *buf = tail;
+    Ok(*head)
+}
+
+fn take_str(buf: &mut &[u8]) -> Result<String, AnalyzerError> {
+    let len = u16::from_le_bytes(take(buf)?) as usize;
+    if buf.len() < len {
+        return Err(AnalyzerError::InvalidTrace("truncated metadata"));
+    }
+
+    let (s, tail) = buf.split_at(len);
+    *buf = tail;
+    String::from_utf8(s.to_vec())
+        .map_err(|_| AnalyzerError::InvalidTrace("invalid utf-8 in metadata"))
+}
+
+pub(crate) fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn take_varint(buf: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (byte, tail) = buf.split_first()?;
+        *buf = tail;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+
+    None
+}
+
+const fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+const fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// CRC-32 (IEEE 802.3) of `data`
+fn crc32(data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 == 0 {
+                    crc >> 1
+                } else {
+                    (crc >> 1) ^ 0xedb8_8320
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+
+    !data.iter().fold(!0, |crc, byte| {
+        TABLE[((crc ^ u32::from(*byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Read exactly `buf.len()` bytes, `Ok(false)` if the reader ended before
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => return Ok(false),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(true)
+}
@@ -0,0 +1,220 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use super::{
+    CHUNK_HEADER_LEN, CHUNK_MAGIC, CHUNK_RECORDS, MAGIC, MAX_CHUNK_LEN, MAX_METADATA_LEN,
+    SessionMetadata, TraceRecord, VERSION, crc32, read_full, take_varint, unzigzag,
+};
+use crate::{
+    Pid,
+    error::{AnalyzerError, Result},
+};
+
+/// Reads a trace file written by [`TraceWriter`](super::TraceWriter), iterating over its records
+///
+/// Chunks failing their checksum are skipped and counted by [`TraceReader::corrupted_chunks`],
+/// a truncated chunk at the end of the file (e.g. the recorder crashed while writing it) ends the iteration
+///
+/// # Examples
+///
+/// ```
+/// use frame_analyzer::trace::{SessionMetadata, TraceReader, TraceRecord, TraceWriter};
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let path = std::env::temp_dir().join("frame-analyzer-doc-crash.fatrace");
+/// let mut writer = TraceWriter::create(&path, &SessionMetadata::default())?;
+/// for i in 0..600 {
+///     writer.write(&TraceRecord::new(i * 1_000_000, 1, 0, 0))?;
+/// }
+/// writer.finish()?;
+///
+/// // the recorder crashed while writing the last chunk
+/// let mut bytes = std::fs::read(&path)?;
+/// bytes.truncate(bytes.len() - 10);
+///
+/// let records = TraceReader::new(bytes.as_slice())?.collect::<Result<Vec<_>, _>>()?;
+/// assert_eq!(records.len(), 512);
+/// # std::fs::remove_file(&path)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TraceReader<R: Read> {
+    inner: R,
+    metadata: SessionMetadata,
+    records: VecDeque<TraceRecord>,
+    corrupted_chunks: usize,
+    finished: bool,
+}
+
+impl TraceReader<BufReader<File>> {
+    /// Open the trace file at `path`
+    ///
+    /// # Errors
+    ///
+    /// - `IOError` if the file cannot be opened or read
+    /// - `InvalidTrace` if the file is not a trace file, or written by a newer version of the format
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path)?;
+        Self::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read> TraceReader<R> {
+    /// Read a trace from any reader, the header is read immediately
+    ///
+    /// # Errors
+    ///
+    /// - `IOError` if reading fails
+    /// - `InvalidTrace` if the header is invalid, or written by a newer version of the format
+    pub fn new(mut inner: R) -> Result<Self> {
+        let mut header = [0; MAGIC.len() + 8];
+        if !read_full(&mut inner, &mut header)? || header[..MAGIC.len()] != MAGIC {
+            return Err(AnalyzerError::InvalidTrace("not a trace file"));
+        }
+
+        let version = u16::from_le_bytes([header[8], header[9]]);
+        if version > VERSION {
+            return Err(AnalyzerError::InvalidTrace("unsupported format version"));
+        }
+
+        let len = u32::from_le_bytes([header[12], header[13], header[14], header[15]]) as usize;
+        if len > MAX_METADATA_LEN {
+            return Err(AnalyzerError::InvalidTrace("corrupted header"));
+        }
+        let mut metadata = vec![0; len];
+        let mut crc = [0; 4];
+        if !read_full(&mut inner, &mut metadata)? || !read_full(&mut inner, &mut crc)? {
+            return Err(AnalyzerError::InvalidTrace("truncated header"));
+        }
+        if crc32(&metadata) != u32::from_le_bytes(crc) {
+            return Err(AnalyzerError::InvalidTrace("corrupted header"));
+        }
+
+        Ok(Self {
+            inner,
+            metadata: SessionMetadata::decode(&metadata)?,
+            records: VecDeque::new(),
+            corrupted_chunks: 0,
+            finished: false,
+        })
+    }
+
+    /// The metadata of the recorded session
+    pub const fn metadata(&self) -> &SessionMetadata {
+        &self.metadata
+    }
+
+    /// Number of chunks skipped so far because they failed their checksum
+    pub const fn corrupted_chunks(&self) -> usize {
+        self.corrupted_chunks
+    }
+
+    /// Read chunks until one is decoded, `Ok(false)` at the end of the trace
+    fn read_chunk(&mut self) -> Result<bool> {
+        loop {
+            let mut header = [0; CHUNK_HEADER_LEN];
+            if !read_full(&mut self.inner, &mut header)? {
+                return Ok(false);
+            }
+
+            let magic = [header[0], header[1], header[2], header[3]];
+            let count = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+            let len = u32::from_le_bytes([header[8], header[9], header[10], header[11]]) as usize;
+            let crc = u32::from_le_bytes([header[12], header[13], header[14], header[15]]);
+
+            // the chunk boundaries are lost with a corrupted chunk header
+            if magic != CHUNK_MAGIC || len > MAX_CHUNK_LEN || count > CHUNK_RECORDS {
+                self.corrupted_chunks += 1;
+                return Ok(false);
+            }
+
+            let mut payload = vec![0; len];
+            if !read_full(&mut self.inner, &mut payload)? {
+                return Ok(false);
+            }
+
+            if crc32(&payload) != crc {
+                self.corrupted_chunks += 1;
+                continue;
+            }
+
+            // the count is not covered by the checksum, it must match the payload exactly
+            match decode_chunk(&payload, count) {
+                Some(records) => {
+                    self.records.extend(records);
+                    return Ok(true);
+                }
+                None => self.corrupted_chunks += 1,
+            }
+        }
+    }
+}
+
+/// The `count` records of a chunk payload, `None` unless they span the whole payload
+fn decode_chunk(payload: &[u8], count: usize) -> Option<Vec<TraceRecord>> {
+    let mut buf = payload;
+    let mut timestamp: u64 = 0;
+    let mut records = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let delta = unzigzag(take_varint(&mut buf)?);
+        let pid = take_varint(&mut buf)?;
+        let surface = take_varint(&mut buf)?;
+        let frametime_ns = take_varint(&mut buf)?;
+
+        timestamp = timestamp.wrapping_add(delta as u64);
+        records.push(TraceRecord::new(
+            timestamp,
+            pid as Pid,
+            surface as usize,
+            frametime_ns,
+        ));
+    }
+
+    buf.is_empty().then_some(records)
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.records.is_empty() {
+            if self.finished {
+                return None;
+            }
+
+            match self.read_chunk() {
+                Ok(true) => (),
+                Ok(false) => self.finished = true,
+                Err(e) => {
+                    self.finished = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        self.records.pop_front().map(Ok)
+    }
+}
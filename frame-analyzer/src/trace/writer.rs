@@ -0,0 +1,156 @@
+/*
+ * Copyright (c) 2024 shadow3aaa@gitbub.com
+ *
+ * This file is part of frame-analyzer-ebpf.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::{
+    fmt,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use super::{
+    CHUNK_HEADER_LEN, CHUNK_MAGIC, CHUNK_RECORDS, MAGIC, MAX_METADATA_LEN, SessionMetadata,
+    TraceRecord, VERSION, crc32, put_varint, zigzag,
+};
+use crate::error::{AnalyzerError, Result};
+
+/// Writes a trace file, see the [module docs](super) for the format
+///
+/// Records are buffered into chunks of [`CHUNK_RECORDS`], a chunk is written & flushed to the underlying writer once it is full,
+/// on [`TraceWriter::flush`], [`TraceWriter::finish`] or drop
+pub struct TraceWriter {
+    inner: Box<dyn Write + Send>,
+    chunk: Vec<u8>,
+    records: u32,
+    last_timestamp: u64,
+}
+
+impl fmt::Debug for TraceWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TraceWriter")
+            .field("records", &self.records)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TraceWriter {
+    /// Create a trace file at `path`, truncating it if it exists
+    ///
+    /// # Errors
+    ///
+    /// - `IOError` if the file cannot be created or the header cannot be written
+    /// - `InvalidTrace` if the metadata does not fit the header, see [`TraceWriter::new`]
+    pub fn create<P: AsRef<Path>>(path: P, metadata: &SessionMetadata) -> Result<Self> {
+        let file = File::create(path)?;
+        Self::new(BufWriter::new(file), metadata)
+    }
+
+    /// Write a trace to any writer, the header is written immediately
+    ///
+    /// # Errors
+    ///
+    /// - `IOError` if the header cannot be written
+    /// - `InvalidTrace` if the encoded metadata is longer than [`MAX_METADATA_LEN`],
+    ///   a string of it is longer than 65535 bytes, or it has more than 65535 pairs
+    pub fn new<W: Write + Send + 'static>(writer: W, metadata: &SessionMetadata) -> Result<Self> {
+        let mut inner: Box<dyn Write + Send> = Box::new(writer);
+        let metadata = metadata.encode()?;
+        if metadata.len() > MAX_METADATA_LEN {
+            return Err(AnalyzerError::InvalidTrace("metadata too long"));
+        }
+
+        let mut header = Vec::with_capacity(MAGIC.len() + 12 + metadata.len());
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        header.extend_from_slice(&metadata);
+        header.extend_from_slice(&crc32(&metadata).to_le_bytes());
+        inner.write_all(&header)?;
+        inner.flush()?;
+
+        Ok(Self {
+            inner,
+            chunk: Vec::new(),
+            records: 0,
+            last_timestamp: 0,
+        })
+    }
+
+    /// Append a record
+    ///
+    /// # Errors
+    ///
+    /// Returns `IOError` if the chunk is full and cannot be written
+    pub fn write(&mut self, record: &TraceRecord) -> Result<()> {
+        let delta = record.timestamp_ns.wrapping_sub(self.last_timestamp) as i64;
+        put_varint(&mut self.chunk, zigzag(delta));
+        put_varint(&mut self.chunk, u64::from(record.pid as u32));
+        put_varint(&mut self.chunk, record.surface as u64);
+        put_varint(&mut self.chunk, record.frametime_ns);
+        self.last_timestamp = record.timestamp_ns;
+        self.records += 1;
+
+        if self.records as usize >= CHUNK_RECORDS {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the pending records as a chunk and flush the underlying writer
+    ///
+    /// # Errors
+    ///
+    /// Returns `IOError` if the chunk cannot be written
+    pub fn flush(&mut self) -> Result<()> {
+        if self.records == 0 {
+            return Ok(());
+        }
+
+        let mut chunk = Vec::with_capacity(CHUNK_HEADER_LEN + self.chunk.len());
+        chunk.extend_from_slice(&CHUNK_MAGIC);
+        chunk.extend_from_slice(&self.records.to_le_bytes());
+        chunk.extend_from_slice(&(self.chunk.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(&crc32(&self.chunk).to_le_bytes());
+        chunk.extend_from_slice(&self.chunk);
+
+        self.chunk.clear();
+        self.records = 0;
+        self.last_timestamp = 0;
+
+        self.inner.write_all(&chunk)?;
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// Write the pending records and close the trace
+    ///
+    /// # Errors
+    ///
+    /// Returns `IOError` if the last chunk cannot be written
+    pub fn finish(mut self) -> Result<()> {
+        self.flush()
+    }
+}
+
+impl Drop for TraceWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
@@ -16,16 +16,39 @@
  * You should have received a copy of the GNU General Public License
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
+use std::{
+    collections::{self, VecDeque},
+    mem,
+    os::unix::io::{AsRawFd, RawFd},
+    ptr,
+};
+
 use aya::{
     Ebpf,
-    maps::{MapData, RingBuf},
+    maps::{Array, HashMap, MapData, MapError, PerCpuHashMap, RingBuf},
     programs::UProbe,
 };
+use frame_analyzer_ebpf_common::{
+    CONFIG_FLAGS, FLAG_KERNEL_FRAMETIME, FrameHistogram, FrameSignal, FrametimeRecord,
+    HISTOGRAM_BINS, SurfaceKey, TimestampRecord,
+};
+
+use crate::{ebpf::load_bpf, error::Result, source::FrameSource};
 
-use crate::{ebpf::load_bpf, error::Result};
+const PER_APP_PROGRAM: &str = "frame_analyzer_ebpf";
+const GLOBAL_PROGRAM: &str = "frame_analyzer_ebpf_global";
+/// Surfaces whose timestamp is rebuilt, the capacity of `LAST_FRAME` in the ebpf program
+const MAX_SURFACES: usize = 1024;
 
 pub struct UprobeHandler {
     bpf: Ebpf,
+    program: &'static str,
+    /// The ebpf program reports [`FrametimeRecord`]s, [`TimestampRecord`]s otherwise
+    kernel_frametime: bool,
+    /// The rebuilt timestamp of the last [`FrametimeRecord`] of every surface
+    timestamps: collections::HashMap<(u32, usize), u64>,
+    /// The histogram totals already handed out by `take_histogram`, per pid
+    taken: collections::HashMap<u32, [u64; HISTOGRAM_BINS]>,
 }
 
 impl Drop for UprobeHandler {
@@ -37,26 +60,44 @@ impl Drop for UprobeHandler {
 }
 
 impl UprobeHandler {
-    pub fn attach_app(pid: i32) -> Result<Self> {
+    pub fn attach_app(pid: i32, flags: u32) -> Result<Self> {
+        Self::attach(PER_APP_PROGRAM, Some(pid), flags)
+    }
+
+    /// Probe every process, only the pids inserted into [`UprobeHandler::pid_filter`] are reported
+    pub fn attach_global(flags: u32) -> Result<Self> {
+        Self::attach(GLOBAL_PROGRAM, None, flags)
+    }
+
+    fn attach(program_name: &'static str, pid: Option<i32>, flags: u32) -> Result<Self> {
         let mut bpf = load_bpf()?;
 
-        let program: &mut UProbe = bpf.program_mut("frame_analyzer_ebpf").unwrap().try_into()?;
+        let mut config: Array<&mut MapData, u32> = Array::try_from(bpf.map_mut("CONFIG").unwrap())?;
+        config.set(CONFIG_FLAGS, flags, 0)?;
+
+        let program: &mut UProbe = bpf.program_mut(program_name).unwrap().try_into()?;
         program.load()?;
         program.attach(
             Some("_ZN7android7Surface11queueBufferEP19ANativeWindowBufferi"),
             0,
             "/system/lib64/libgui.so",
-            Some(pid),
-        ).or_else(|e1| {
+            pid,
+        ).or_else(|_| {
             program.attach(
                 Some("_ZN7android7Surface11queueBufferEP19ANativeWindowBufferiPNS_24SurfaceQueueBufferOutputE"),
                 0,
                 "/system/lib64/libgui.so",
-                Some(pid),
+                pid,
             )
         })?;
 
-        Ok(Self { bpf })
+        Ok(Self {
+            bpf,
+            program: program_name,
+            kernel_frametime: flags & FLAG_KERNEL_FRAMETIME != 0,
+            timestamps: collections::HashMap::new(),
+            taken: collections::HashMap::new(),
+        })
     }
 
     pub fn ring(&mut self) -> Result<RingBuf<&mut MapData>> {
@@ -64,12 +105,166 @@ impl UprobeHandler {
         Ok(ring)
     }
 
+    pub fn pid_filter(&mut self) -> Result<HashMap<&mut MapData, u32, u8>> {
+        let filter: HashMap<&mut MapData, u32, u8> =
+            HashMap::try_from(self.bpf.map_mut("PID_FILTER").unwrap())?;
+        Ok(filter)
+    }
+
+    /// Tell the ebpf program which surface of `pid` is selected, only used with `FLAG_SUPPRESS_UNSELECTED`
+    pub fn select_surface(&mut self, pid: u32, buffer: usize) -> Result<()> {
+        let mut selected: HashMap<&mut MapData, u32, u64> =
+            HashMap::try_from(self.bpf.map_mut("SELECTED_SURFACE").unwrap())?;
+        selected.insert(pid, buffer as u64, 0)?;
+        Ok(())
+    }
+
+    /// Read the histogram of `pid` summed over all cpus, counting only the frames since the previous call
+    ///
+    /// The ebpf counters are never reset, the frames already taken are subtracted instead,
+    /// so no frame recorded while reading is lost
+    pub fn take_histogram(&mut self, pid: u32) -> Result<[u64; HISTOGRAM_BINS]> {
+        let histograms: PerCpuHashMap<&mut MapData, u32, FrameHistogram> =
+            PerCpuHashMap::try_from(self.bpf.map_mut("HISTOGRAM").unwrap())?;
+
+        let values = match histograms.get(&pid, 0) {
+            Ok(values) => values,
+            Err(MapError::KeyNotFound) => return Ok([0; HISTOGRAM_BINS]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut totals = [0; HISTOGRAM_BINS];
+        for histogram in values.iter() {
+            for (total, count) in totals.iter_mut().zip(histogram.bins) {
+                *total += count;
+            }
+        }
+
+        let taken = self.taken.entry(pid).or_insert([0; HISTOGRAM_BINS]);
+        let mut bins = [0; HISTOGRAM_BINS];
+        for ((bin, total), taken) in bins.iter_mut().zip(totals).zip(taken.iter()) {
+            *bin = total.saturating_sub(*taken);
+        }
+        *taken = totals;
+
+        Ok(bins)
+    }
+
+    /// Number of signals of `pid` lost because the ring buffer was full, summed over all cpus
+    pub fn dropped(&mut self, pid: u32) -> Result<u64> {
+        let dropped: PerCpuHashMap<&mut MapData, u32, u64> =
+            PerCpuHashMap::try_from(self.bpf.map_mut("DROPPED").unwrap())?;
+
+        match dropped.get(&pid, 0) {
+            Ok(values) => Ok(values.iter().sum()),
+            Err(MapError::KeyNotFound) => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Remove every map entry of `pid`, so a recycled pid starts from a clean state
+    pub fn forget(&mut self, pid: u32) -> Result<()> {
+        let mut last_frame: HashMap<&mut MapData, SurfaceKey, u64> =
+            HashMap::try_from(self.bpf.map_mut("LAST_FRAME").unwrap())?;
+        let surfaces: Vec<SurfaceKey> = last_frame
+            .keys()
+            .filter_map(std::result::Result::ok)
+            .filter(|key| key.pid == pid)
+            .collect();
+        for key in &surfaces {
+            ignore_missing(last_frame.remove(key))?;
+        }
+
+        let mut selected: HashMap<&mut MapData, u32, u64> =
+            HashMap::try_from(self.bpf.map_mut("SELECTED_SURFACE").unwrap())?;
+        ignore_missing(selected.remove(&pid))?;
+
+        let mut histograms: PerCpuHashMap<&mut MapData, u32, FrameHistogram> =
+            PerCpuHashMap::try_from(self.bpf.map_mut("HISTOGRAM").unwrap())?;
+        ignore_missing(histograms.remove(&pid))?;
+        self.taken.remove(&pid);
+        self.timestamps
+            .retain(|&(surface_pid, _), _| surface_pid != pid);
+
+        let mut dropped: PerCpuHashMap<&mut MapData, u32, u64> =
+            PerCpuHashMap::try_from(self.bpf.map_mut("DROPPED").unwrap())?;
+        ignore_missing(dropped.remove(&pid))
+    }
+
     fn get_program(&mut self) -> Result<&mut UProbe> {
-        let program: &mut UProbe = self
-            .bpf
-            .program_mut("frame_analyzer_ebpf")
-            .unwrap()
-            .try_into()?;
+        let program: &mut UProbe = self.bpf.program_mut(self.program).unwrap().try_into()?;
         Ok(program)
     }
 }
+
+/// A map entry that is already gone is as good as removed
+fn ignore_missing(result: std::result::Result<(), MapError>) -> Result<()> {
+    match result {
+        Ok(()) | Err(MapError::KeyNotFound) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+impl FrameSource for UprobeHandler {
+    fn raw_fd(&mut self) -> Result<RawFd> {
+        Ok(self.ring()?.as_raw_fd())
+    }
+
+    fn drain(&mut self, signals: &mut VecDeque<FrameSignal>) -> Result<()> {
+        let mut ring: RingBuf<&mut MapData> =
+            RingBuf::try_from(self.bpf.map_mut("RING_BUF").unwrap())?;
+
+        while let Some(item) = ring.next() {
+            let signal = if self.kernel_frametime {
+                read::<FrametimeRecord>(&item)
+                    .map(|record| rebuild_timestamp(&mut self.timestamps, &record))
+            } else {
+                read::<TimestampRecord>(&item)
+                    .map(|record| FrameSignal::new(record.ktime_ns, record.buffer, 0, record.pid))
+            };
+            signals.extend(signal);
+        }
+
+        Ok(())
+    }
+}
+
+/// A frametime record has no timestamp, add its frametime to the timestamp of the previous frame of the surface
+///
+/// The first frame of a surface, and one after a saturated frametime, are stamped with the current time instead
+fn rebuild_timestamp(
+    timestamps: &mut collections::HashMap<(u32, usize), u64>,
+    record: &FrametimeRecord,
+) -> FrameSignal {
+    let key = (record.pid, record.buffer);
+    if timestamps.len() >= MAX_SURFACES && !timestamps.contains_key(&key) {
+        timestamps.clear();
+    }
+
+    let frametime_ns = u64::from(record.frametime_ns);
+    let timestamp = match timestamps.get(&key) {
+        Some(last) if record.frametime_ns != u32::MAX => last + frametime_ns,
+        _ => monotonic_ns(),
+    };
+    timestamps.insert(key, timestamp);
+
+    FrameSignal::new(timestamp, record.buffer, frametime_ns, record.pid)
+}
+
+/// `CLOCK_MONOTONIC` in nanoseconds, the clock of `bpf_ktime_get_ns`
+fn monotonic_ns() -> u64 {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &raw mut time);
+    }
+    time.tv_sec as u64 * 1_000_000_000 + time.tv_nsec as u64
+}
+
+/// Decode a ring buffer record, `None` if the item is too short for it
+fn read<T: Copy>(item: &[u8]) -> Option<T> {
+    (item.len() >= mem::size_of::<T>())
+        .then(|| unsafe { ptr::read_unaligned(item.as_ptr().cast::<T>()) })
+}